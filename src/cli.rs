@@ -0,0 +1,770 @@
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use shadowpuppet::export::is_same_file;
+use shadowpuppet::{
+    Channel, Curve, DepthMapBuilder, ExportFormat, Exporter, Filament, FilamentPlan, Filter,
+    FilterKind, HeightmapExporter, Lithophane, Mesh, MeshOptions, MeshStyle, ObjExporter,
+    PlyExporter, Projection, Quantizer, StencilOptions, Stencils, SvgExporter, ThreeMfExporter,
+    Tone,
+};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+#[derive(Parser)]
+#[command(
+    name = "shadowpuppet",
+    about = "Turn bitmap photos into depth-mapped 3D meshes"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Convert images to meshes without opening a window
+    Convert(Convert),
+}
+
+#[derive(Args)]
+struct Convert {
+    /// Input images; quoted glob patterns such as "scans/*.png" are expanded
+    #[arg(required = true)]
+    inputs: Vec<String>,
+
+    /// Output file, or a directory when converting several inputs
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Output format; guessed from the output extension when omitted
+    #[arg(short, long, value_enum)]
+    format: Option<Format>,
+
+    /// Number of depth layers
+    #[arg(short, long, default_value_t = 8, value_parser = clap::value_parser!(u8).range(2..))]
+    layers: u8,
+
+    /// Pixel property mapped to height
+    #[arg(long, value_enum, default_value_t = Source::Rec601)]
+    channel: Source,
+
+    /// Make darker areas higher, as for lithophanes
+    #[arg(long)]
+    invert: bool,
+
+    /// Keep the image's full depth precision for smooth meshes, lithophanes
+    /// and heightmaps instead of snapping to layers
+    #[arg(long)]
+    continuous: bool,
+
+    /// Keep transparent pixels in the mesh instead of cutting them out
+    #[arg(long)]
+    ignore_alpha: bool,
+
+    /// How the boundaries between layers are placed
+    #[arg(long, value_enum, default_value_t = Placement::Uniform)]
+    quantize: Placement,
+
+    /// Depth, from 0 to 1, where the next layer up begins; repeat once per
+    /// boundary, one fewer than the layers, to place them by hand
+    #[arg(long = "threshold", value_name = "DEPTH", conflicts_with = "quantize")]
+    thresholds: Vec<f32>,
+
+    /// Merge regions of one layer smaller than this many pixels into the layer
+    /// around them
+    #[arg(long, conflicts_with = "min_region_mm2")]
+    min_region_px: Option<usize>,
+
+    /// Merge regions of one layer smaller than this many square millimetres
+    /// into the layer around them
+    #[arg(long)]
+    min_region_mm2: Option<f32>,
+
+    /// Channel value, from 0 to 1, that becomes the lowest depth
+    #[arg(long)]
+    black_point: Option<f32>,
+
+    /// Channel value, from 0 to 1, that becomes the highest depth
+    #[arg(long)]
+    white_point: Option<f32>,
+
+    /// Midtone adjustment after the black and white points; above 1 lifts them
+    #[arg(long)]
+    gamma: Option<f32>,
+
+    /// Point the tone curve passes through, as IN:OUT from 0 to 1 such as
+    /// "0.25:0.4"; repeat for more points
+    #[arg(long = "curve-point", value_name = "IN:OUT", value_parser = parse_curve_point)]
+    curve_points: Vec<[f32; 2]>,
+
+    /// Smooth or sharpen the image before quantizing, against speckled layers
+    #[arg(long, value_enum, default_value_t = Smoothing::None)]
+    filter: Smoothing,
+
+    /// Filter neighbourhood in pixels: the blur's standard deviation or the
+    /// median's reach
+    #[arg(long)]
+    filter_radius: Option<f32>,
+
+    /// Largest depth difference, from 0 to 1, the bilateral filter smooths across
+    #[arg(long)]
+    filter_range: Option<f32>,
+
+    /// Strength of the sharpen filter
+    #[arg(long)]
+    sharpen_amount: Option<f32>,
+
+    /// Surface between neighbouring pixels
+    #[arg(long, value_enum, default_value_t = Style::Smooth)]
+    style: Style,
+
+    /// Surface to wrap the relief around; the width becomes its circumference
+    #[arg(long, value_enum, default_value_t = Wrap::Flat)]
+    wrap: Wrap,
+
+    /// Add a base plate and side walls so the mesh is printable
+    #[arg(long)]
+    solid: bool,
+
+    /// Build a separate solid body per layer, one object each in 3MF files
+    #[arg(long)]
+    split_layers: bool,
+
+    /// Write PLY files as a point cloud of the full-resolution height field
+    #[arg(long)]
+    points: bool,
+
+    /// Add each vertex's depth layer to PLY files as a `layer` property
+    #[arg(long)]
+    layer_property: bool,
+
+    /// Model width in millimetres
+    #[arg(long, conflicts_with = "height_mm")]
+    width_mm: Option<f32>,
+
+    /// Model height in millimetres; the width follows the image's aspect ratio
+    #[arg(long)]
+    height_mm: Option<f32>,
+
+    /// Height difference between the lowest and highest layer, in millimetres
+    #[arg(long)]
+    relief_mm: Option<f32>,
+
+    /// Material below the lowest layer, in millimetres
+    #[arg(long)]
+    min_thickness_mm: Option<f32>,
+
+    /// Keep one vertex per pixel instead of merging flat regions
+    #[arg(long)]
+    no_simplify: bool,
+
+    /// Height error in millimetres allowed when merging smooth faces
+    #[arg(long)]
+    tolerance_mm: Option<f32>,
+
+    /// Build a backlit lithophane panel, thicker where the image is darker
+    #[arg(long)]
+    lithophane: bool,
+
+    /// Lithophane thickness behind the brightest areas, in millimetres
+    #[arg(long, requires = "lithophane")]
+    min_wall_mm: Option<f32>,
+
+    /// Lithophane thickness behind the darkest areas and of the frame, in millimetres
+    #[arg(long, requires = "lithophane")]
+    max_wall_mm: Option<f32>,
+
+    /// Width of the lithophane's frame, in millimetres
+    #[arg(long, requires = "lithophane")]
+    frame_mm: Option<f32>,
+
+    /// Shape of the lithophane panel
+    #[arg(long, value_enum, requires = "lithophane")]
+    curve: Option<Shape>,
+
+    /// Angle an arc-curved lithophane spans, in degrees
+    #[arg(long, requires = "lithophane")]
+    arc_degrees: Option<f32>,
+
+    /// Don't map the source image onto OBJ and GLB meshes
+    #[arg(long)]
+    no_texture: bool,
+
+    /// Filament for the next depth layer up, as COLOUR[:TD] such as "#1a1a1a:0.6";
+    /// repeat from the bottom layer up, the last one continuing to the top. A swap
+    /// schedule is written next to the mesh.
+    #[arg(long = "filament", value_name = "COLOUR[:TD]", value_parser = parse_filament)]
+    filaments: Vec<Filament>,
+
+    /// Slicer layer height used to number the layers in the swap schedule
+    #[arg(long, requires = "filaments")]
+    layer_height_mm: Option<f32>,
+
+    /// Write each SVG stencil sheet to its own file instead of one layered file
+    #[arg(long)]
+    separate_sheets: bool,
+
+    /// Width of the border around each SVG stencil sheet, in millimetres
+    #[arg(long)]
+    sheet_frame_mm: Option<f32>,
+
+    /// Width of the bridges holding stencil islands in place, in millimetres
+    #[arg(long)]
+    bridge_mm: Option<f32>,
+
+    /// Leave stencil islands loose instead of bridging them to their sheet
+    #[arg(long)]
+    no_bridges: bool,
+
+    /// Bits per pixel of PNG and TIFF heightmaps
+    #[arg(long, value_enum, default_value_t = BitDepth::Sixteen)]
+    bit_depth: BitDepth,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Obj,
+    /// Binary glTF with the photo embedded
+    Glb,
+    Stl,
+    StlAscii,
+    /// Multi-material package; see --split-layers
+    #[value(name = "3mf")]
+    ThreeMf,
+    /// Vertices coloured from the photo; see --points and --layer-property
+    Ply,
+    PlyAscii,
+    /// Shadow box stencils, one cut sheet per layer
+    Svg,
+    /// Grayscale heightmap of the depth map; see --bit-depth
+    Png,
+    Tiff,
+}
+
+impl Format {
+    fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?;
+        if extension.eq_ignore_ascii_case("svg") {
+            return Some(Format::Svg);
+        } else if extension.eq_ignore_ascii_case("png") {
+            return Some(Format::Png);
+        } else if extension.eq_ignore_ascii_case("tif") || extension.eq_ignore_ascii_case("tiff") {
+            return Some(Format::Tiff);
+        }
+        ExportFormat::from_path(path).map(|format| match format {
+            ExportFormat::Obj => Format::Obj,
+            ExportFormat::Glb => Format::Glb,
+            ExportFormat::Stl => Format::Stl,
+            ExportFormat::StlAscii => Format::StlAscii,
+            ExportFormat::ThreeMf => Format::ThreeMf,
+            ExportFormat::Ply => Format::Ply,
+            ExportFormat::PlyAscii => Format::PlyAscii,
+        })
+    }
+
+    // Mesh format to write, or None for stencils and heightmaps
+    fn mesh(self) -> Option<ExportFormat> {
+        match self {
+            Format::Obj => Some(ExportFormat::Obj),
+            Format::Glb => Some(ExportFormat::Glb),
+            Format::Stl => Some(ExportFormat::Stl),
+            Format::StlAscii => Some(ExportFormat::StlAscii),
+            Format::ThreeMf => Some(ExportFormat::ThreeMf),
+            Format::Ply => Some(ExportFormat::Ply),
+            Format::PlyAscii => Some(ExportFormat::PlyAscii),
+            Format::Svg | Format::Png | Format::Tiff => None,
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Format::Svg => "svg",
+            Format::Png => "png",
+            Format::Tiff => "tiff",
+            _ => self.mesh().map_or("obj", ExportFormat::extension),
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum BitDepth {
+    #[value(name = "8")]
+    Eight,
+    #[value(name = "16")]
+    Sixteen,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Source {
+    Rec601,
+    Rec709,
+    Lightness,
+    Value,
+    Red,
+    Green,
+    Blue,
+    Alpha,
+}
+
+impl From<Source> for Channel {
+    fn from(source: Source) -> Self {
+        match source {
+            Source::Rec601 => Channel::Rec601,
+            Source::Rec709 => Channel::Rec709,
+            Source::Lightness => Channel::Lightness,
+            Source::Value => Channel::Value,
+            Source::Red => Channel::Red,
+            Source::Green => Channel::Green,
+            Source::Blue => Channel::Blue,
+            Source::Alpha => Channel::Alpha,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Placement {
+    /// Equally spaced
+    Uniform,
+    /// Multi-level Otsu
+    Otsu,
+    /// K-means clusters of the depths
+    Kmeans,
+    /// Equal numbers of pixels per layer
+    Equalize,
+}
+
+impl From<Placement> for Quantizer {
+    fn from(placement: Placement) -> Self {
+        match placement {
+            Placement::Uniform => Quantizer::Uniform,
+            Placement::Otsu => Quantizer::Otsu,
+            Placement::Kmeans => Quantizer::KMeans,
+            Placement::Equalize => Quantizer::Equalize,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Smoothing {
+    None,
+    Gaussian,
+    /// Edge-preserving blur
+    Bilateral,
+    /// Removes isolated specks
+    Median,
+    /// Unsharp mask
+    Sharpen,
+}
+
+impl From<Smoothing> for FilterKind {
+    fn from(smoothing: Smoothing) -> Self {
+        match smoothing {
+            Smoothing::None => FilterKind::None,
+            Smoothing::Gaussian => FilterKind::Gaussian,
+            Smoothing::Bilateral => FilterKind::Bilateral,
+            Smoothing::Median => FilterKind::Median,
+            Smoothing::Sharpen => FilterKind::Sharpen,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Style {
+    Smooth,
+    Terraced,
+}
+
+impl From<Style> for MeshStyle {
+    fn from(style: Style) -> Self {
+        match style {
+            Style::Smooth => MeshStyle::Smooth,
+            Style::Terraced => MeshStyle::Terraced,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Wrap {
+    Flat,
+    Cylinder,
+    Sphere,
+    Dome,
+}
+
+impl From<Wrap> for Projection {
+    fn from(wrap: Wrap) -> Self {
+        match wrap {
+            Wrap::Flat => Projection::Flat,
+            Wrap::Cylinder => Projection::Cylinder,
+            Wrap::Sphere => Projection::Sphere,
+            Wrap::Dome => Projection::Dome,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Shape {
+    Flat,
+    Arc,
+    Cylinder,
+}
+
+impl From<Shape> for Curve {
+    fn from(shape: Shape) -> Self {
+        match shape {
+            Shape::Flat => Curve::Flat,
+            Shape::Arc => Curve::Arc,
+            Shape::Cylinder => Curve::Cylinder,
+        }
+    }
+}
+
+// Run the command line front-end. Exits with 1 if any input failed to convert
+// and 2 on usage errors.
+pub fn run() -> ExitCode {
+    let Command::Convert(convert) = Cli::parse().command;
+
+    let inputs = match expand_inputs(&convert.inputs) {
+        Ok(inputs) => inputs,
+        Err(e) => {
+            eprintln!("shadowpuppet: {}", e);
+            return ExitCode::from(2);
+        }
+    };
+
+    // Several inputs need somewhere to put several outputs. A name with an
+    // extension is taken for a file unless it's a directory already or ends in
+    // a separator.
+    let output_dir = match &convert.output {
+        Some(output) if inputs.len() > 1 || output.is_dir() => {
+            let separated = output.to_string_lossy().ends_with(std::path::is_separator);
+            if output.extension().is_some() && !output.is_dir() && !separated {
+                eprintln!(
+                    "shadowpuppet: {}: several inputs need an output directory",
+                    output.display()
+                );
+                return ExitCode::from(2);
+            }
+            if let Err(e) = std::fs::create_dir_all(output) {
+                eprintln!("shadowpuppet: {}: {}", output.display(), e);
+                return ExitCode::from(2);
+            }
+            Some(output.as_path())
+        }
+        _ => None,
+    };
+
+    let format = convert
+        .format
+        .or_else(|| {
+            convert
+                .output
+                .as_deref()
+                .filter(|_| output_dir.is_none())
+                .and_then(Format::from_path)
+        })
+        .unwrap_or(Format::Obj);
+
+    let mut failed = false;
+    for input in &inputs {
+        let output = match (output_dir, &convert.output) {
+            (Some(dir), _) => dir.join(output_name(input, format)),
+            (None, Some(output)) => output.clone(),
+            (None, None) => input.with_file_name(output_name(input, format)),
+        };
+
+        // Whatever the names, never write over an input
+        let texture = (matches!(format, Format::Obj) && !convert.no_texture)
+            .then(|| ObjExporter::texture_path(&output));
+        let clash = std::iter::once(&output)
+            .chain(&texture)
+            .find(|path| inputs.iter().any(|input| is_same_file(path, input)));
+        if let Some(clash) = clash {
+            eprintln!(
+                "shadowpuppet: {}: {} would overwrite an input",
+                input.display(),
+                clash.display()
+            );
+            failed = true;
+            continue;
+        }
+
+        match convert_one(&convert, input, &output, format) {
+            Ok(()) => println!("{} -> {}", input.display(), output.display()),
+            Err(e) => {
+                eprintln!("shadowpuppet: {}: {}", input.display(), e);
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+fn convert_one(
+    convert: &Convert,
+    input: &Path,
+    output: &Path,
+    format: Format,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Depth is read at the image's own bit depth
+    let img = image::open(input)?;
+
+    let mut options = MeshOptions {
+        style: convert.style.into(),
+        projection: convert.wrap.into(),
+        split_layers: convert.split_layers,
+        solid: convert.solid,
+        simplify: !convert.no_simplify,
+        ..MeshOptions::default()
+    };
+    if let Some(tolerance_mm) = convert.tolerance_mm {
+        options.tolerance_mm = tolerance_mm;
+    }
+
+    let dimensions = &mut options.dimensions;
+    if let Some(width_mm) = convert.width_mm {
+        dimensions.width_mm = width_mm;
+    }
+    if let Some(height_mm) = convert.height_mm {
+        dimensions.width_mm = height_mm * img.width() as f32 / img.height().max(1) as f32;
+    }
+    if let Some(relief_mm) = convert.relief_mm {
+        dimensions.relief_mm = relief_mm;
+    }
+    if let Some(min_thickness_mm) = convert.min_thickness_mm {
+        dimensions.min_thickness_mm = min_thickness_mm;
+    }
+
+    if convert.lithophane {
+        let mut lithophane = Lithophane::default();
+        if let Some(min_wall_mm) = convert.min_wall_mm {
+            lithophane.min_thickness_mm = min_wall_mm;
+        }
+        if let Some(max_wall_mm) = convert.max_wall_mm {
+            lithophane.max_thickness_mm = max_wall_mm;
+        }
+        if let Some(frame_mm) = convert.frame_mm {
+            lithophane.frame_mm = frame_mm;
+        }
+        if let Some(curve) = convert.curve {
+            lithophane.curve = curve.into();
+        }
+        if let Some(arc_degrees) = convert.arc_degrees {
+            lithophane.arc_degrees = arc_degrees;
+        }
+        options.lithophane = Some(lithophane);
+    }
+
+    let ply = matches!(format, Format::Ply | Format::PlyAscii);
+    if ply && convert.points {
+        // One point per pixel, without the base and walls
+        options.simplify = false;
+        options.solid = false;
+        options.split_layers = false;
+        options.style = MeshStyle::Smooth;
+        options.lithophane = None;
+    }
+
+    let mut tone = Tone::default();
+    if let Some(black) = convert.black_point {
+        tone.black = black;
+    }
+    if let Some(white) = convert.white_point {
+        tone.white = white;
+    }
+    if let Some(gamma) = convert.gamma {
+        tone.gamma = gamma;
+    }
+    tone.curve.extend(&convert.curve_points);
+    tone.curve.sort_by(|a, b| a[0].total_cmp(&b[0]));
+
+    let mut filter = Filter {
+        kind: convert.filter.into(),
+        ..Filter::default()
+    };
+    if let Some(radius_px) = convert.filter_radius {
+        filter.radius_px = radius_px;
+    }
+    if let Some(range) = convert.filter_range {
+        filter.range = range;
+    }
+    if let Some(amount) = convert.sharpen_amount {
+        filter.amount = amount;
+    }
+
+    if !convert.thresholds.is_empty() && convert.thresholds.len() + 1 != convert.layers as usize {
+        return Err(format!(
+            "{} layers need {} thresholds",
+            convert.layers,
+            convert.layers - 1
+        )
+        .into());
+    }
+
+    let mut builder = DepthMapBuilder::new()
+        .layers(convert.layers)
+        .channel(convert.channel.into())
+        .invert(convert.invert)
+        .continuous(convert.continuous)
+        .cut_out(!convert.ignore_alpha)
+        .tone(tone)
+        .filter(filter)
+        .quantizer(convert.quantize.into());
+    if let Some(min_region_px) = convert.min_region_px {
+        builder = builder.min_region_px(min_region_px);
+    } else if let Some(min_region_mm2) = convert.min_region_mm2 {
+        let pixel_mm = options.dimensions.width_mm / img.width().max(1) as f32;
+        let min_region_px = (min_region_mm2 / (pixel_mm * pixel_mm)).ceil() as usize;
+        builder = builder.min_region_px(min_region_px);
+    }
+    if !convert.thresholds.is_empty() {
+        builder = builder.thresholds(convert.thresholds.clone());
+    }
+    let depth = builder.build(&img);
+
+    if matches!(format, Format::Png | Format::Tiff) {
+        let sixteen_bit = matches!(convert.bit_depth, BitDepth::Sixteen);
+        HeightmapExporter { sixteen_bit }.export(&depth, output)?;
+        return Ok(());
+    }
+
+    let Some(format) = format.mesh() else {
+        let mut stencil = StencilOptions {
+            width_mm: options.dimensions.width_mm,
+            connect: !convert.no_bridges,
+            ..StencilOptions::default()
+        };
+        if let Some(sheet_frame_mm) = convert.sheet_frame_mm {
+            stencil.frame_mm = sheet_frame_mm;
+        }
+        if let Some(bridge_mm) = convert.bridge_mm {
+            stencil.bridge_mm = bridge_mm;
+        }
+        let stencils = Stencils::build(&depth, &stencil);
+        let layered = !convert.separate_sheets;
+        SvgExporter { layered }.export(&stencils, output)?;
+        return Ok(());
+    };
+
+    let plan = convert.filaments.last().map(|&last| {
+        let mut plan = FilamentPlan::grayscale(depth.layers);
+        for (level, filament) in plan.filaments.iter_mut().enumerate() {
+            *filament = convert.filaments.get(level).copied().unwrap_or(last);
+        }
+        if let Some(layer_height_mm) = convert.layer_height_mm {
+            plan.layer_height_mm = layer_height_mm;
+        }
+        plan
+    });
+
+    let mesh = Mesh::build(&depth, &options);
+    let texture = (!convert.no_texture).then(|| img.to_rgba8());
+    let texture = texture.as_ref();
+    let exporter: Box<dyn Exporter> = match (format, &plan) {
+        // 3MF layers take their colours from the filaments
+        (ExportFormat::ThreeMf, Some(plan)) => Box::new(ThreeMfExporter {
+            colors: plan
+                .filaments
+                .iter()
+                .map(|filament| filament.color)
+                .collect(),
+        }),
+        (ExportFormat::Ply | ExportFormat::PlyAscii, _) => Box::new(PlyExporter {
+            ascii: format == ExportFormat::PlyAscii,
+            texture,
+            layers: convert.layer_property.then_some(&depth),
+            points: convert.points,
+        }),
+        _ => format.exporter(texture),
+    };
+    exporter.export(&mesh, output)?;
+
+    if let Some(plan) = &plan {
+        plan.write_schedule(&options.dimensions, &FilamentPlan::schedule_path(output))?;
+    }
+
+    Ok(())
+}
+
+// Expand glob patterns the shell left alone; plain paths pass through untouched
+fn expand_inputs(patterns: &[String]) -> Result<Vec<PathBuf>, String> {
+    let mut inputs = Vec::new();
+
+    for pattern in patterns {
+        if !pattern.contains(['*', '?', '[']) {
+            inputs.push(PathBuf::from(pattern));
+            continue;
+        }
+
+        let paths = glob::glob(pattern).map_err(|e| format!("{}: {}", pattern, e))?;
+        let before = inputs.len();
+        for path in paths {
+            inputs.push(path.map_err(|e| e.to_string())?);
+        }
+        if inputs.len() == before {
+            return Err(format!("{}: no files match", pattern));
+        }
+    }
+
+    Ok(inputs)
+}
+
+// Parse "#rrggbb" with an optional ":TD" transmission distance in millimetres
+fn parse_filament(value: &str) -> Result<Filament, String> {
+    let (color, transmission) = match value.split_once(':') {
+        Some((color, transmission)) => (color, Some(transmission)),
+        None => (value, None),
+    };
+
+    let hex = color.trim_start_matches('#');
+    let rgb = u32::from_str_radix(hex, 16)
+        .ok()
+        .filter(|_| hex.len() == 6)
+        .ok_or_else(|| format!("{} is not a #rrggbb colour", color))?;
+    let transmission_mm = match transmission {
+        Some(transmission) => transmission
+            .parse()
+            .map_err(|_| format!("{} is not a transmission distance", transmission))?,
+        None => 1.0,
+    };
+
+    Ok(Filament {
+        color: [(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8],
+        transmission_mm,
+    })
+}
+
+fn parse_curve_point(value: &str) -> Result<[f32; 2], String> {
+    let point = value
+        .split_once(':')
+        .and_then(|(input, output)| Some([input.parse().ok()?, output.parse().ok()?]))
+        .ok_or_else(|| format!("{} is not an IN:OUT pair", value))?;
+    if point.iter().all(|v: &f32| (0.0..=1.0).contains(v)) {
+        Ok(point)
+    } else {
+        Err(format!("{} is outside 0 to 1", value))
+    }
+}
+
+fn output_name(input: &Path, format: Format) -> PathBuf {
+    let stem = input.file_stem().unwrap_or(input.as_os_str());
+    // Heightmaps would otherwise overwrite PNG and TIFF inputs, and no other
+    // format may take its input's name either
+    let shared = input
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case(format.extension()));
+    let suffix = match format {
+        Format::Png | Format::Tiff => Some("-height"),
+        _ if shared => Some("-converted"),
+        _ => None,
+    };
+    let mut stem = stem.to_owned();
+    if let Some(suffix) = suffix {
+        stem.push(suffix);
+    }
+    PathBuf::from(stem).with_extension(format.extension())
+}
@@ -0,0 +1,556 @@
+use crate::filter::Filter;
+use crate::quantize::Quantizer;
+use crate::tone::Tone;
+use image::{DynamicImage, ImageBuffer, Pixel};
+
+/// Quantized depth of every pixel of an image, as a layer index from 0 (lowest)
+/// to `layers - 1` (highest).
+#[derive(Clone, Debug)]
+pub struct DepthMap {
+    pub width: usize,
+    pub height: usize,
+    pub layers: u8,
+    levels: Vec<u8>,
+    /// Unquantized depth of every pixel in 0.0..=1.0, when continuous
+    values: Option<Vec<f32>>,
+    /// Which pixels are opaque, when any were cut out
+    mask: Option<Vec<bool>>,
+    thresholds: Vec<f32>,
+    /// Which pixels speckle cleanup moved to another layer, when it ran
+    merged: Option<Vec<bool>>,
+}
+
+impl DepthMap {
+    /// Layer index of the pixel at (`x`, `y`)
+    pub fn level(&self, x: usize, y: usize) -> u8 {
+        self.levels[y * self.width + x]
+    }
+
+    /// Layer indices in row-major order
+    pub fn levels(&self) -> &[u8] {
+        &self.levels
+    }
+
+    /// Depth of a pixel scaled to 0.0..=1.0; between layers when the map is
+    /// continuous
+    pub fn fraction(&self, x: usize, y: usize) -> f32 {
+        match &self.values {
+            Some(values) => values[y * self.width + x],
+            None => self.level(x, y) as f32 / (self.layers as f32 - 1.0),
+        }
+    }
+
+    /// Whether a pixel is part of the relief; transparent pixels are cut out
+    pub fn opaque(&self, x: usize, y: usize) -> bool {
+        self.mask
+            .as_ref()
+            .is_none_or(|mask| mask[y * self.width + x])
+    }
+
+    /// Whether any pixel was cut out
+    pub fn is_masked(&self) -> bool {
+        self.mask.is_some()
+    }
+
+    /// Whether depths keep the source's precision instead of snapping to layers
+    pub fn is_continuous(&self) -> bool {
+        self.values.is_some()
+    }
+
+    /// Depths where each layer above the lowest begins, ascending
+    pub fn thresholds(&self) -> &[f32] {
+        &self.thresholds
+    }
+
+    /// Whether speckle cleanup moved a pixel into a neighbouring layer
+    pub fn is_merged(&self, x: usize, y: usize) -> bool {
+        self.merged
+            .as_ref()
+            .is_some_and(|merged| merged[y * self.width + x])
+    }
+
+    /// Depth of a pixel as an 8-bit gray value, as shown in the preview
+    pub fn gray(&self, x: usize, y: usize) -> u8 {
+        (self.fraction(x, y) * 255.0).round() as u8
+    }
+}
+
+/// Which property of a pixel becomes its depth
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Channel {
+    /// Luma with Rec. 601 weights
+    #[default]
+    Rec601,
+    /// Luma with Rec. 709 weights
+    Rec709,
+    /// Perceptual lightness, CIE L*
+    Lightness,
+    /// HSV value, the brightest of the three channels
+    Value,
+    Red,
+    Green,
+    Blue,
+    Alpha,
+}
+
+impl Channel {
+    pub const ALL: [Channel; 8] = [
+        Channel::Rec601,
+        Channel::Rec709,
+        Channel::Lightness,
+        Channel::Value,
+        Channel::Red,
+        Channel::Green,
+        Channel::Blue,
+        Channel::Alpha,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Channel::Rec601 => "Luma (Rec. 601)",
+            Channel::Rec709 => "Luma (Rec. 709)",
+            Channel::Lightness => "Lightness (L*)",
+            Channel::Value => "Value (HSV)",
+            Channel::Red => "Red",
+            Channel::Green => "Green",
+            Channel::Blue => "Blue",
+            Channel::Alpha => "Alpha",
+        }
+    }
+
+    /// Read this channel from RGBA components in 0.0..=1.0
+    pub fn sample(self, [r, g, b, a]: [f32; 4]) -> f32 {
+        match self {
+            Channel::Rec601 => 0.299 * r + 0.587 * g + 0.114 * b,
+            Channel::Rec709 => 0.2126 * r + 0.7152 * g + 0.0722 * b,
+            Channel::Lightness => {
+                let y = 0.2126 * linear(r) + 0.7152 * linear(g) + 0.0722 * linear(b);
+                let f = if y > 216.0 / 24389.0 {
+                    y.cbrt()
+                } else {
+                    (24389.0 / 27.0 * y + 16.0) / 116.0
+                };
+                (116.0 * f - 16.0) / 100.0
+            }
+            Channel::Value => r.max(g).max(b),
+            Channel::Red => r,
+            Channel::Green => g,
+            Channel::Blue => b,
+            Channel::Alpha => a,
+        }
+    }
+}
+
+// Subpixel types of decoded images, scaled to 0.0..=1.0
+trait Component {
+    fn unit(self) -> f32;
+}
+
+impl Component for u8 {
+    fn unit(self) -> f32 {
+        self as f32 / u8::MAX as f32
+    }
+}
+
+impl Component for u16 {
+    fn unit(self) -> f32 {
+        self as f32 / u16::MAX as f32
+    }
+}
+
+impl Component for f32 {
+    fn unit(self) -> f32 {
+        self
+    }
+}
+
+// Undo the sRGB transfer curve
+fn linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Settings for turning an image into a [`DepthMap`]. By default brighter
+/// pixels end up on higher layers.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DepthMapBuilder {
+    pub layers: u8,
+    pub channel: Channel,
+    /// Put darker pixels on higher layers, as for lithophanes and engravings
+    pub invert: bool,
+    /// Keep every pixel's exact depth for smooth meshes, lithophanes and
+    /// heightmaps; terraces, stencils and filaments still use the layers
+    pub continuous: bool,
+    /// Leave transparent pixels out of the relief, unless alpha is the source
+    pub cut_out: bool,
+    /// Levels and tone curve applied to the channel before anything else
+    pub tone: Tone,
+    /// Smoothing or sharpening applied before quantizing
+    pub filter: Filter,
+    /// Where the boundaries between layers fall
+    pub quantizer: Quantizer,
+    /// Boundaries between layers for manual quantization, one fewer than
+    /// the layers
+    pub thresholds: Vec<f32>,
+    /// Regions of one layer with fewer pixels than this merge into the layer
+    /// around them, so no unprintable spikes or pits are left; 0 keeps them
+    pub min_region_px: usize,
+}
+
+impl Default for DepthMapBuilder {
+    fn default() -> Self {
+        DepthMapBuilder {
+            layers: 8,
+            channel: Channel::default(),
+            invert: false,
+            continuous: false,
+            cut_out: true,
+            tone: Tone::default(),
+            filter: Filter::default(),
+            quantizer: Quantizer::default(),
+            thresholds: Vec::new(),
+            min_region_px: 0,
+        }
+    }
+}
+
+impl DepthMapBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of depth layers, at least 2
+    pub fn layers(mut self, layers: u8) -> Self {
+        self.layers = layers.max(2);
+        self
+    }
+
+    pub fn channel(mut self, channel: Channel) -> Self {
+        self.channel = channel;
+        self
+    }
+
+    pub fn invert(mut self, invert: bool) -> Self {
+        self.invert = invert;
+        self
+    }
+
+    pub fn continuous(mut self, continuous: bool) -> Self {
+        self.continuous = continuous;
+        self
+    }
+
+    pub fn cut_out(mut self, cut_out: bool) -> Self {
+        self.cut_out = cut_out;
+        self
+    }
+
+    pub fn tone(mut self, tone: Tone) -> Self {
+        self.tone = tone;
+        self
+    }
+
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    pub fn quantizer(mut self, quantizer: Quantizer) -> Self {
+        self.quantizer = quantizer;
+        self
+    }
+
+    /// Place the layer boundaries by hand; see [`Quantizer::Manual`]
+    pub fn thresholds(mut self, thresholds: Vec<f32>) -> Self {
+        self.quantizer = Quantizer::Manual;
+        self.thresholds = thresholds;
+        self
+    }
+
+    pub fn min_region_px(mut self, min_region_px: usize) -> Self {
+        self.min_region_px = min_region_px;
+        self
+    }
+
+    /// Quantize an image at its own bit depth, so 16-bit and floating point
+    /// sources keep their precision
+    pub fn build(&self, img: &DynamicImage) -> DepthMap {
+        let layers = self.layers.max(2);
+
+        let (mut values, mask) = self.samples(img);
+        for value in &mut values {
+            let toned = self.tone.map(*value);
+            *value = if self.invert { 1.0 - toned } else { toned }.clamp(0.0, 1.0);
+        }
+
+        let cut_out = self.cut_out && self.channel != Channel::Alpha;
+        let (width, height) = (img.width() as usize, img.height() as usize);
+        // Cut-out pixels would otherwise bleed into the edges of the relief
+        self.filter
+            .apply(&mut values, width, height, cut_out.then_some(&mask[..]));
+        let thresholds = self.quantizer.thresholds(
+            &values,
+            cut_out.then_some(&mask[..]),
+            layers,
+            &self.thresholds,
+        );
+        let mut levels: Vec<u8> = values
+            .iter()
+            .map(|&value| thresholds.partition_point(|&threshold| threshold <= value) as u8)
+            .collect();
+        let merged = (self.min_region_px > 1).then(|| {
+            merge_specks(
+                &mut levels,
+                &mut values,
+                width,
+                height,
+                cut_out.then_some(&mask[..]),
+                self.min_region_px,
+            )
+        });
+
+        DepthMap {
+            width,
+            height,
+            layers,
+            levels,
+            values: self.continuous.then_some(values),
+            mask: (cut_out && mask.contains(&false)).then_some(mask),
+            thresholds,
+            merged,
+        }
+    }
+
+    /// Number of pixels at each of 256 tones after levels and before the curve,
+    /// as drawn behind a curve editor. Cut-out pixels aren't counted.
+    pub fn histogram(&self, img: &DynamicImage) -> [u32; 256] {
+        let (values, mask) = self.samples(img);
+        let cut_out = self.cut_out && self.channel != Channel::Alpha;
+
+        let mut histogram = [0; 256];
+        for (value, opaque) in values.into_iter().zip(mask) {
+            if opaque || !cut_out {
+                histogram[(self.tone.levels(value) * 255.0).round() as usize] += 1;
+            }
+        }
+        histogram
+    }
+
+    // The channel's value and whether the pixel is opaque, for every pixel
+    fn samples(&self, img: &DynamicImage) -> (Vec<f32>, Vec<bool>) {
+        match img {
+            DynamicImage::ImageLuma8(img) => self.samples_from(img),
+            DynamicImage::ImageLumaA8(img) => self.samples_from(img),
+            DynamicImage::ImageRgb8(img) => self.samples_from(img),
+            DynamicImage::ImageRgba8(img) => self.samples_from(img),
+            DynamicImage::ImageLuma16(img) => self.samples_from(img),
+            DynamicImage::ImageLumaA16(img) => self.samples_from(img),
+            DynamicImage::ImageRgb16(img) => self.samples_from(img),
+            DynamicImage::ImageRgba16(img) => self.samples_from(img),
+            DynamicImage::ImageRgb32F(img) => self.samples_from(img),
+            DynamicImage::ImageRgba32F(img) => self.samples_from(img),
+            img => self.samples_from(&img.to_rgba32f()),
+        }
+    }
+
+    fn samples_from<P>(&self, img: &ImageBuffer<P, Vec<P::Subpixel>>) -> (Vec<f32>, Vec<bool>)
+    where
+        P: Pixel,
+        P::Subpixel: Component,
+    {
+        let count = img.width() as usize * img.height() as usize;
+        let mut values = Vec::with_capacity(count);
+        let mut mask = Vec::with_capacity(count);
+        for pixel in img.pixels() {
+            let rgba = pixel.to_rgba().0.map(Component::unit);
+            values.push(self.channel.sample(rgba).clamp(0.0, 1.0));
+            mask.push(rgba[3] >= 0.5);
+        }
+        (values, mask)
+    }
+}
+
+// Merge every 4-connected region of one layer smaller than `min_area` into the
+// layer it shares the most edge with, taking that layer's depth along the
+// boundary. Merges can leave new small regions, so this repeats a few times.
+// Returns which pixels moved.
+fn merge_specks(
+    levels: &mut [u8],
+    values: &mut [f32],
+    width: usize,
+    height: usize,
+    mask: Option<&[bool]>,
+    min_area: usize,
+) -> Vec<bool> {
+    let opaque = |i: usize| mask.is_none_or(|mask| mask[i]);
+    let neighbours = move |i: usize| {
+        let (x, y) = (i % width, i / width);
+        [
+            (x > 0).then(|| i - 1),
+            (x + 1 < width).then(|| i + 1),
+            (y > 0).then(|| i - width),
+            (y + 1 < height).then(|| i + width),
+        ]
+        .into_iter()
+        .flatten()
+    };
+
+    let mut merged = vec![false; levels.len()];
+    let mut region = Vec::new();
+    let mut stack = Vec::new();
+    for _ in 0..8 {
+        let mut visited = vec![false; levels.len()];
+        let mut changed = false;
+
+        for seed in 0..levels.len() {
+            if visited[seed] || !opaque(seed) {
+                continue;
+            }
+
+            // Flood the region of this layer around the seed
+            let level = levels[seed];
+            region.clear();
+            stack.clear();
+            stack.push(seed);
+            visited[seed] = true;
+            while let Some(i) = stack.pop() {
+                region.push(i);
+                for j in neighbours(i) {
+                    if !visited[j] && opaque(j) && levels[j] == level {
+                        visited[j] = true;
+                        stack.push(j);
+                    }
+                }
+            }
+            if region.len() >= min_area {
+                continue;
+            }
+
+            // Count the edges shared with each other layer and their depth there
+            let mut edges = [0u32; 256];
+            let mut depths = [0.0f32; 256];
+            for &i in &region {
+                for j in neighbours(i) {
+                    if opaque(j) && levels[j] != level {
+                        edges[levels[j] as usize] += 1;
+                        depths[levels[j] as usize] += values[j];
+                    }
+                }
+            }
+            // Ties go to the layer closest in height
+            let Some(target) = (0..256)
+                .filter(|&l| edges[l] > 0)
+                .max_by_key(|&l| (edges[l], std::cmp::Reverse(l.abs_diff(level as usize))))
+            else {
+                continue;
+            };
+
+            let depth = depths[target] / edges[target] as f32;
+            for &i in &region {
+                levels[i] = target as u8;
+                values[i] = depth;
+                merged[i] = true;
+            }
+            changed = true;
+        }
+
+        if !changed {
+            break;
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    // Depth map from rows of layer digits, with '.' for cut-out pixels
+    fn depth_map(rows: &[&str], builder: DepthMapBuilder) -> DepthMap {
+        let (width, height) = (rows[0].len() as u32, rows.len() as u32);
+        let steps = (builder.layers - 1) as f32;
+        let img = RgbaImage::from_fn(width, height, |x, y| {
+            match rows[y as usize].as_bytes()[x as usize] {
+                b'.' => Rgba([0, 0, 0, 0]),
+                digit => {
+                    let gray = ((digit - b'0') as f32 / steps * 255.0).round() as u8;
+                    Rgba([gray, gray, gray, 255])
+                }
+            }
+        });
+        builder.cut_out(true).build(&DynamicImage::ImageRgba8(img))
+    }
+
+    fn rows(depth: &DepthMap) -> Vec<String> {
+        (0..depth.height)
+            .map(|y| {
+                (0..depth.width)
+                    .map(|x| match depth.opaque(x, y) {
+                        true => (b'0' + depth.level(x, y)) as char,
+                        false => '.',
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn single_pixel_merges_into_its_surroundings() {
+        let builder = DepthMapBuilder::new().layers(2).min_region_px(2);
+        let depth = depth_map(&["000", "010", "000"], builder);
+
+        assert_eq!(rows(&depth), ["000", "000", "000"]);
+        assert!(depth.is_merged(1, 1));
+        assert!(!depth.is_merged(0, 0) && !depth.is_merged(2, 1));
+    }
+
+    #[test]
+    fn regions_at_the_minimum_stay() {
+        let builder = DepthMapBuilder::new().layers(2).min_region_px(2);
+        let depth = depth_map(&["0000", "0110", "0000"], builder);
+
+        assert_eq!(rows(&depth), ["0000", "0110", "0000"]);
+        assert!(!depth.is_merged(1, 1));
+    }
+
+    #[test]
+    fn equal_borders_go_to_the_closest_layer() {
+        // The pit borders layers 1 and 3 along two edges each
+        let builder = DepthMapBuilder::new().layers(4).min_region_px(2);
+        let depth = depth_map(&["33333", "11033", "11111"], builder);
+
+        assert_eq!(rows(&depth), ["33333", "11133", "11111"]);
+        assert!(depth.is_merged(2, 1));
+    }
+
+    #[test]
+    fn cut_out_pixels_neither_merge_nor_count() {
+        // Cut-out pixels sit on layer 0 underneath, along three of the speck's edges
+        let builder = DepthMapBuilder::new().layers(4).min_region_px(2);
+        let depth = depth_map(&[".....", "..2..", "33333"], builder);
+
+        assert_eq!(rows(&depth), [".....", "..3..", "33333"]);
+        assert!(depth.is_merged(2, 1));
+        for x in 0..5 {
+            assert!(!depth.is_merged(x, 0));
+        }
+    }
+
+    #[test]
+    fn continuous_depths_follow_the_merge() {
+        let builder = DepthMapBuilder::new()
+            .layers(3)
+            .min_region_px(2)
+            .continuous(true);
+        let depth = depth_map(&["222", "202", "211"], builder);
+
+        assert_eq!(rows(&depth), ["222", "222", "211"]);
+        assert_eq!(depth.fraction(1, 1), depth.fraction(0, 1));
+        assert_eq!(depth.fraction(1, 1), 1.0);
+        assert!((depth.fraction(1, 2) - 0.5).abs() < 0.01);
+    }
+}
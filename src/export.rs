@@ -0,0 +1,113 @@
+mod glb;
+mod heightmap;
+mod obj;
+mod ply;
+mod stl;
+mod svg;
+mod threemf;
+
+pub use glb::GlbExporter;
+pub use heightmap::HeightmapExporter;
+pub use obj::ObjExporter;
+pub use ply::PlyExporter;
+pub use stl::StlExporter;
+pub use svg::SvgExporter;
+pub use threemf::ThreeMfExporter;
+
+use crate::mesh::Mesh;
+use image::RgbaImage;
+use std::path::Path;
+
+/// Writes a [`Mesh`] to disk in one file format
+pub trait Exporter {
+    fn export(&self, mesh: &Mesh, path: &Path) -> std::io::Result<()>;
+}
+
+/// Whether `output` names the existing file at `input`, so writing it would
+/// destroy the source
+pub fn is_same_file(output: &Path, input: &Path) -> bool {
+    match (output.canonicalize(), input.canonicalize()) {
+        (Ok(output), Ok(input)) => output == input,
+        _ => false,
+    }
+}
+
+/// File formats offered by the front-ends
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    Obj,
+    Glb,
+    Stl,
+    StlAscii,
+    ThreeMf,
+    Ply,
+    PlyAscii,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 7] = [
+        ExportFormat::Obj,
+        ExportFormat::Glb,
+        ExportFormat::Stl,
+        ExportFormat::StlAscii,
+        ExportFormat::ThreeMf,
+        ExportFormat::Ply,
+        ExportFormat::PlyAscii,
+    ];
+
+    /// Name of the file chooser filter for this format
+    pub fn label(self) -> &'static str {
+        match self {
+            ExportFormat::Obj => "Wavefront OBJ",
+            ExportFormat::Glb => "glTF binary (GLB)",
+            ExportFormat::Stl => "STL (binary)",
+            ExportFormat::StlAscii => "STL (ASCII)",
+            ExportFormat::ThreeMf => "3MF",
+            ExportFormat::Ply => "PLY (binary)",
+            ExportFormat::PlyAscii => "PLY (ASCII)",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Obj => "obj",
+            ExportFormat::Glb => "glb",
+            ExportFormat::Stl | ExportFormat::StlAscii => "stl",
+            ExportFormat::ThreeMf => "3mf",
+            ExportFormat::Ply | ExportFormat::PlyAscii => "ply",
+        }
+    }
+
+    pub fn from_label(label: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|format| format.label() == label)
+    }
+
+    /// Guess the format from a file extension; STL and PLY default to binary
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        Self::ALL
+            .into_iter()
+            .find(|format| format.extension() == extension)
+    }
+
+    /// Exporter for this format. `texture` is the source photo, used by formats
+    /// that can carry one.
+    pub fn exporter<'a>(self, texture: Option<&'a RgbaImage>) -> Box<dyn Exporter + 'a> {
+        match self {
+            ExportFormat::Obj => Box::new(ObjExporter { texture }),
+            ExportFormat::Glb => Box::new(GlbExporter { texture }),
+            ExportFormat::Stl => Box::new(StlExporter { ascii: false }),
+            ExportFormat::StlAscii => Box::new(StlExporter { ascii: true }),
+            ExportFormat::ThreeMf => Box::new(ThreeMfExporter::default()),
+            ExportFormat::Ply => Box::new(PlyExporter {
+                texture,
+                ..PlyExporter::default()
+            }),
+            ExportFormat::PlyAscii => Box::new(PlyExporter {
+                ascii: true,
+                texture,
+                ..PlyExporter::default()
+            }),
+        }
+    }
+}
@@ -0,0 +1,292 @@
+use super::Exporter;
+use crate::mesh::Mesh;
+use image::{ImageFormat, RgbaImage};
+use std::fs::File;
+use std::io::{BufWriter, Cursor, Write};
+use std::path::Path;
+
+// Constants from the glTF 2.0 specification
+const MAGIC: &[u8; 4] = b"glTF";
+const CHUNK_JSON: &[u8; 4] = b"JSON";
+const CHUNK_BIN: &[u8; 4] = b"BIN\0";
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const UNSIGNED_SHORT: u32 = 5123;
+const UNSIGNED_INT: u32 = 5125;
+const FLOAT: u32 = 5126;
+const LINEAR: u32 = 9729;
+const CLAMP_TO_EDGE: u32 = 33071;
+
+/// Binary glTF 2.0 with smooth normals, kept sharp where faces meet at a crease,
+/// for web viewers and game engines.
+/// With a texture, the photo is embedded as a PNG and mapped onto the mesh.
+/// Millimetres are scaled to the metres glTF expects.
+pub struct GlbExporter<'a> {
+    pub texture: Option<&'a RgbaImage>,
+}
+
+impl Exporter for GlbExporter<'_> {
+    fn export(&self, mesh: &Mesh, path: &Path) -> std::io::Result<()> {
+        if mesh.triangles.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "the mesh has no faces to write",
+            ));
+        }
+
+        let mut bin = Vec::new();
+        let mut views = Vec::new();
+
+        let (sources, normals, triangles) = shade(mesh);
+        let positions = push_view(&mut bin, &mut views, Some(ARRAY_BUFFER), |bin| {
+            for &i in &sources {
+                for value in mesh.positions[i as usize] {
+                    bin.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+        });
+        let normals = push_view(&mut bin, &mut views, Some(ARRAY_BUFFER), |bin| {
+            for value in normals.iter().flatten() {
+                bin.extend_from_slice(&value.to_le_bytes());
+            }
+        });
+        // glTF puts the texture origin at the top left, OBJ at the bottom left
+        let uvs = push_view(&mut bin, &mut views, Some(ARRAY_BUFFER), |bin| {
+            for &i in &sources {
+                let [u, v] = mesh.uvs[i as usize];
+                bin.extend_from_slice(&u.to_le_bytes());
+                bin.extend_from_slice(&(1.0 - v).to_le_bytes());
+            }
+        });
+        // Short indices while they fit, short of the reserved 0xffff
+        let short = sources.len() < u16::MAX as usize;
+        let indices = push_view(&mut bin, &mut views, Some(ELEMENT_ARRAY_BUFFER), |bin| {
+            for &index in triangles.iter().flatten() {
+                match short {
+                    true => bin.extend_from_slice(&(index as u16).to_le_bytes()),
+                    false => bin.extend_from_slice(&index.to_le_bytes()),
+                }
+            }
+        });
+        let image = match self.texture {
+            Some(img) => {
+                let mut png = Cursor::new(Vec::new());
+                img.write_to(&mut png, ImageFormat::Png)
+                    .map_err(std::io::Error::other)?;
+                Some(push_view(&mut bin, &mut views, None, |bin| {
+                    bin.extend_from_slice(png.get_ref())
+                }))
+            }
+            None => None,
+        };
+
+        let (min, max) = bounds(sources.iter().map(|&i| mesh.positions[i as usize]));
+        let vertices = sources.len();
+        let accessors = [
+            format!(
+                r#"{{"bufferView":{},"componentType":{},"count":{},"type":"VEC3","min":[{},{},{}],"max":[{},{},{}]}}"#,
+                positions, FLOAT, vertices, min[0], min[1], min[2], max[0], max[1], max[2]
+            ),
+            format!(
+                r#"{{"bufferView":{},"componentType":{},"count":{},"type":"VEC3"}}"#,
+                normals, FLOAT, vertices
+            ),
+            format!(
+                r#"{{"bufferView":{},"componentType":{},"count":{},"type":"VEC2"}}"#,
+                uvs, FLOAT, vertices
+            ),
+            format!(
+                r#"{{"bufferView":{},"componentType":{},"count":{},"type":"SCALAR"}}"#,
+                indices,
+                if short { UNSIGNED_SHORT } else { UNSIGNED_INT },
+                triangles.len() * 3
+            ),
+        ];
+
+        let mut json = String::new();
+        json.push_str(r#"{"asset":{"version":"2.0","generator":"Shadowpuppet"},"#);
+        json.push_str(r#""scene":0,"scenes":[{"nodes":[0]}],"#);
+        json.push_str(r#""nodes":[{"name":"Relief","mesh":0,"scale":[0.001,0.001,0.001]}],"#);
+        json.push_str(r#""meshes":[{"name":"Relief","primitives":[{"attributes":{"POSITION":0,"NORMAL":1,"TEXCOORD_0":2},"indices":3,"material":0}]}],"#);
+        let base_color = match image {
+            Some(_) => r#""baseColorTexture":{"index":0},"#,
+            None => "",
+        };
+        // The relief may be open at the back, so show both sides
+        json.push_str(&format!(
+            r#""materials":[{{"name":"Photo","pbrMetallicRoughness":{{{}"metallicFactor":0,"roughnessFactor":1}},"doubleSided":true}}],"#,
+            base_color
+        ));
+        if let Some(view) = image {
+            json.push_str(&format!(
+                r#""samplers":[{{"magFilter":{0},"minFilter":{0},"wrapS":{1},"wrapT":{1}}}],"#,
+                LINEAR, CLAMP_TO_EDGE
+            ));
+            json.push_str(r#""textures":[{"sampler":0,"source":0}],"#);
+            json.push_str(&format!(
+                r#""images":[{{"bufferView":{},"mimeType":"image/png"}}],"#,
+                view
+            ));
+        }
+        json.push_str(&format!(r#""accessors":[{}],"#, accessors.join(",")));
+        json.push_str(&format!(r#""bufferViews":[{}],"#, views.join(",")));
+        json.push_str(&format!(r#""buffers":[{{"byteLength":{}}}]}}"#, bin.len()));
+
+        // Chunks are padded to four bytes, JSON with spaces
+        let mut json = json.into_bytes();
+        json.resize(json.len().next_multiple_of(4), b' ');
+        let length = 12 + 8 + json.len() + 8 + bin.len();
+
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(MAGIC)?;
+        file.write_all(&2u32.to_le_bytes())?;
+        file.write_all(&(length as u32).to_le_bytes())?;
+        for (kind, chunk) in [(CHUNK_JSON, &json), (CHUNK_BIN, &bin)] {
+            file.write_all(&(chunk.len() as u32).to_le_bytes())?;
+            file.write_all(kind)?;
+            file.write_all(chunk)?;
+        }
+        file.flush()
+    }
+}
+
+// Append a buffer view filled by `fill`, aligned to four bytes, and return its index
+fn push_view(
+    bin: &mut Vec<u8>,
+    views: &mut Vec<String>,
+    target: Option<u32>,
+    fill: impl FnOnce(&mut Vec<u8>),
+) -> usize {
+    let offset = bin.len();
+    fill(bin);
+    let length = bin.len() - offset;
+    bin.resize(bin.len().next_multiple_of(4), 0);
+
+    let target = target.map_or(String::new(), |target| format!(r#","target":{}"#, target));
+    views.push(format!(
+        r#"{{"buffer":0,"byteOffset":{},"byteLength":{}{}}}"#,
+        offset, length, target
+    ));
+    views.len() - 1
+}
+
+// Vertices where faces meet at a sharp angle, such as terrace steps and the
+// walls of a solid, get a copy per face carrying its facet normal; the others
+// keep the averaged normal. Returns the source vertex and normal of each vertex
+// written, and the triangles over them.
+fn shade(mesh: &Mesh) -> (Vec<u32>, Vec<[f32; 3]>, Vec<[u32; 3]>) {
+    // One of two faces meeting at an angle lies at least half of it away from
+    // their average, so this splits creases of about 50 degrees and more
+    const CREASE: f32 = 0.9;
+
+    let smooth = mesh.vertex_normals();
+    let facets: Vec<[f32; 3]> = mesh
+        .triangles
+        .iter()
+        .map(|&triangle| mesh.facet_normal(triangle))
+        .collect();
+    let mut creased = vec![false; mesh.positions.len()];
+    for (triangle, &[x, y, z]) in mesh.triangles.iter().zip(&facets) {
+        for &i in triangle {
+            let [nx, ny, nz] = smooth[i as usize];
+            if [x, y, z] != [0.0; 3] && nx * x + ny * y + nz * z < CREASE {
+                creased[i as usize] = true;
+            }
+        }
+    }
+
+    let mut sources = Vec::new();
+    let mut normals = Vec::new();
+    let mut indices = vec![u32::MAX; mesh.positions.len()];
+    let triangles = mesh
+        .triangles
+        .iter()
+        .zip(&facets)
+        .map(|(triangle, &facet)| {
+            triangle.map(|i| {
+                let index = &mut indices[i as usize];
+                if creased[i as usize] || *index == u32::MAX {
+                    sources.push(i);
+                    normals.push(match creased[i as usize] {
+                        true => facet,
+                        false => smooth[i as usize],
+                    });
+                    *index = sources.len() as u32 - 1;
+                }
+                *index
+            })
+        })
+        .collect();
+
+    (sources, normals, triangles)
+}
+
+// Position accessors must carry their bounding box
+fn bounds(positions: impl Iterator<Item = [f32; 3]>) -> ([f32; 3], [f32; 3]) {
+    let mut min = [f32::MAX; 3];
+    let mut max = [f32::MIN; 3];
+    for position in positions {
+        for axis in 0..3 {
+            min[axis] = min[axis].min(position[axis]);
+            max[axis] = max[axis].max(position[axis]);
+        }
+    }
+    (min, max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Unit square facing up, and with `wall` another dropping from its far edge
+    fn step(wall: bool) -> Mesh {
+        let mut mesh = Mesh {
+            positions: vec![
+                [0.0, 0.0, 1.0],
+                [1.0, 0.0, 1.0],
+                [1.0, 1.0, 1.0],
+                [0.0, 1.0, 1.0],
+                [1.0, 0.0, 0.0],
+                [1.0, 1.0, 0.0],
+            ],
+            uvs: vec![[0.0, 0.0]; 6],
+            triangles: vec![[0, 1, 2], [0, 2, 3]],
+            layers: Vec::new(),
+        };
+        if wall {
+            mesh.triangles.extend([[1, 4, 5], [1, 5, 2]]);
+        }
+        mesh
+    }
+
+    #[test]
+    fn flat_faces_share_their_vertices() {
+        let (sources, normals, triangles) = shade(&step(false));
+        assert_eq!(sources, [0, 1, 2, 3]);
+        assert!(normals.iter().all(|&n| n == [0.0, 0.0, 1.0]));
+        assert_eq!(triangles, [[0, 1, 2], [0, 2, 3]]);
+    }
+
+    #[test]
+    fn creases_keep_their_facet_normals() {
+        let mesh = step(true);
+        let (sources, normals, triangles) = shade(&mesh);
+        assert!(sources.len() > 6);
+        for (&triangle, &original) in triangles.iter().zip(&mesh.triangles) {
+            assert_eq!(triangle.map(|i| sources[i as usize]), original);
+            for i in triangle {
+                assert_eq!(normals[i as usize], mesh.facet_normal(original));
+            }
+        }
+    }
+
+    #[test]
+    fn empty_mesh_is_refused() {
+        let path = std::env::temp_dir().join("shadowpuppet-empty.glb");
+        let error = GlbExporter { texture: None }
+            .export(&Mesh::default(), &path)
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(!path.exists());
+    }
+}
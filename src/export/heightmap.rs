@@ -0,0 +1,40 @@
+use crate::depth::DepthMap;
+use image::{ImageBuffer, ImageFormat, Luma};
+use std::path::Path;
+
+/// Grayscale heightmap of a depth map, black at the lowest layer and white at
+/// the highest, for displacement maps, terrain and CNC software. The file is
+/// PNG or TIFF after its extension.
+pub struct HeightmapExporter {
+    /// 16 bits per pixel instead of 8
+    pub sixteen_bit: bool,
+}
+
+impl HeightmapExporter {
+    pub fn export(&self, depth: &DepthMap, path: &Path) -> std::io::Result<()> {
+        let format = ImageFormat::from_path(path)
+            .ok()
+            .filter(|format| matches!(format, ImageFormat::Png | ImageFormat::Tiff))
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "heightmaps are saved as .png or .tiff",
+                )
+            })?;
+
+        let (width, height) = (depth.width as u32, depth.height as u32);
+        let result = if self.sixteen_bit {
+            ImageBuffer::from_fn(width, height, |x, y| {
+                let fraction = depth.fraction(x as usize, y as usize);
+                Luma([(fraction * u16::MAX as f32).round() as u16])
+            })
+            .save_with_format(path, format)
+        } else {
+            ImageBuffer::from_fn(width, height, |x, y| {
+                Luma([depth.gray(x as usize, y as usize)])
+            })
+            .save_with_format(path, format)
+        };
+        result.map_err(std::io::Error::other)
+    }
+}
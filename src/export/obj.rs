@@ -0,0 +1,87 @@
+use super::Exporter;
+use crate::mesh::Mesh;
+use image::RgbaImage;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Wavefront .obj with a companion .mtl. With a texture, the photo is saved
+/// next to it as a .png and mapped onto the mesh.
+pub struct ObjExporter<'a> {
+    pub texture: Option<&'a RgbaImage>,
+}
+
+impl ObjExporter<'_> {
+    /// Where the texture of an OBJ written to `obj_path` goes. The suffix keeps
+    /// it from replacing a photo of the same name.
+    pub fn texture_path(obj_path: &Path) -> PathBuf {
+        let mut name = obj_path.file_stem().unwrap_or_default().to_owned();
+        name.push("-texture.png");
+        obj_path.with_file_name(name)
+    }
+}
+
+impl Exporter for ObjExporter<'_> {
+    fn export(&self, mesh: &Mesh, path: &Path) -> std::io::Result<()> {
+        save_as_obj(mesh, self.texture, path)
+    }
+}
+
+fn save_as_obj(mesh: &Mesh, texture: Option<&RgbaImage>, path: &Path) -> std::io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+
+    // The material library and texture sit next to the OBJ and are named after it
+    let mtl_path = path.with_extension("mtl");
+    let texture_path = ObjExporter::texture_path(path);
+    let texture_name = match texture {
+        Some(img) => {
+            img.save(&texture_path).map_err(std::io::Error::other)?;
+            Some(file_name(&texture_path))
+        }
+        None => None,
+    };
+    save_mtl(&mtl_path, texture_name.as_deref())?;
+
+    writeln!(file, "mtllib {}", file_name(&mtl_path))?;
+    writeln!(file, "usemtl plane_material")?;
+
+    for [x, y, z] in &mesh.positions {
+        writeln!(file, "v {} {} {}", x, y, z)?;
+    }
+
+    for [u, v] in &mesh.uvs {
+        writeln!(file, "vt {} {}", u, v)?;
+    }
+
+    // OBJ indices are 1-based and each position shares its texture coordinate's index
+    for [a, b, c] in &mesh.triangles {
+        let (a, b, c) = (a + 1, b + 1, c + 1);
+        writeln!(file, "f {0}/{0} {1}/{1} {2}/{2}", a, b, c)?;
+    }
+
+    file.flush()
+}
+
+// Write the companion .mtl, optionally mapping a diffuse texture
+fn save_mtl(path: &Path, texture: Option<&str>) -> std::io::Result<()> {
+    let mut file = File::create(path)?;
+
+    writeln!(file, "newmtl plane_material")?;
+    writeln!(file, "Ka 0.0 0.0 0.0")?;
+    writeln!(file, "Kd 1.0 1.0 1.0")?;
+    writeln!(file, "Ks 0.0 0.0 0.0")?;
+    writeln!(file, "d 1.0")?;
+    writeln!(file, "illum 1")?;
+    if let Some(texture) = texture {
+        writeln!(file, "map_Kd {}", texture)?;
+    }
+
+    Ok(())
+}
+
+// OBJ and MTL reference their sidecars by bare file name
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
@@ -0,0 +1,214 @@
+use super::Exporter;
+use crate::depth::DepthMap;
+use crate::mesh::Mesh;
+use image::RgbaImage;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Binary or ASCII .ply. With a texture, every vertex takes the colour of the
+/// photo under it; with a depth map, its layer index too, as a `layer`
+/// property. In points mode only the vertices are written, as a point cloud.
+#[derive(Default)]
+pub struct PlyExporter<'a> {
+    pub ascii: bool,
+    pub texture: Option<&'a RgbaImage>,
+    pub layers: Option<&'a DepthMap>,
+    pub points: bool,
+}
+
+impl Exporter for PlyExporter<'_> {
+    fn export(&self, mesh: &Mesh, path: &Path) -> std::io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+
+        writeln!(file, "ply")?;
+        match self.ascii {
+            true => writeln!(file, "format ascii 1.0")?,
+            false => writeln!(file, "format binary_little_endian 1.0")?,
+        }
+        writeln!(file, "comment Shadowpuppet relief")?;
+        writeln!(file, "element vertex {}", mesh.positions.len())?;
+        for axis in ["x", "y", "z"] {
+            writeln!(file, "property float {}", axis)?;
+        }
+        if self.texture.is_some() {
+            for channel in ["red", "green", "blue"] {
+                writeln!(file, "property uchar {}", channel)?;
+            }
+        }
+        if self.layers.is_some() {
+            writeln!(file, "property uchar layer")?;
+        }
+        if !self.points {
+            writeln!(file, "element face {}", mesh.triangles.len())?;
+            writeln!(file, "property list uchar int vertex_indices")?;
+        }
+        writeln!(file, "end_header")?;
+
+        for (i, position) in mesh.positions.iter().enumerate() {
+            let uv = mesh.uvs.get(i).copied().unwrap_or_default();
+            let color = self.texture.map(|img| {
+                let (x, y) = pixel(uv, img.width() as usize, img.height() as usize);
+                let [r, g, b, _] = img.get_pixel(x as u32, y as u32).0;
+                [r, g, b]
+            });
+            let layer = self.layers.map(|depth| {
+                let (x, y) = pixel(uv, depth.width, depth.height);
+                depth.level(x, y)
+            });
+
+            if self.ascii {
+                let [x, y, z] = position;
+                write!(file, "{} {} {}", x, y, z)?;
+                if let Some([r, g, b]) = color {
+                    write!(file, " {} {} {}", r, g, b)?;
+                }
+                if let Some(layer) = layer {
+                    write!(file, " {}", layer)?;
+                }
+                writeln!(file)?;
+            } else {
+                for value in position {
+                    file.write_all(&value.to_le_bytes())?;
+                }
+                if let Some(color) = color {
+                    file.write_all(&color)?;
+                }
+                if let Some(layer) = layer {
+                    file.write_all(&[layer])?;
+                }
+            }
+        }
+
+        if !self.points {
+            for [a, b, c] in &mesh.triangles {
+                if self.ascii {
+                    writeln!(file, "3 {} {} {}", a, b, c)?;
+                } else {
+                    file.write_all(&[3])?;
+                    for index in [a, b, c] {
+                        file.write_all(&(*index as i32).to_le_bytes())?;
+                    }
+                }
+            }
+        }
+
+        file.flush()
+    }
+}
+
+// Pixel under a texture coordinate, which has its origin at the bottom left
+fn pixel([u, v]: [f32; 2], width: usize, height: usize) -> (usize, usize) {
+    let x = (u * width as f32).floor() as usize;
+    let y = ((1.0 - v) * height as f32).floor() as usize;
+    (
+        x.min(width.saturating_sub(1)),
+        y.min(height.saturating_sub(1)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::depth::DepthMapBuilder;
+    use image::{DynamicImage, Rgba};
+
+    // Unit square over a 2 by 2 image, split into two triangles
+    fn square() -> Mesh {
+        Mesh {
+            positions: vec![
+                [0.0, 0.0, 0.0],
+                [1.0, 0.0, 0.0],
+                [1.0, 1.0, 0.0],
+                [0.0, 1.0, 0.0],
+            ],
+            uvs: vec![[0.25, 0.25], [0.75, 0.25], [0.75, 0.75], [0.25, 0.75]],
+            triangles: vec![[0, 1, 2], [0, 2, 3]],
+            layers: Vec::new(),
+        }
+    }
+
+    fn export(exporter: &PlyExporter, name: &str) -> Vec<u8> {
+        let path = std::env::temp_dir().join(name);
+        exporter.export(&square(), &path).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        bytes
+    }
+
+    // Header lines up to and including end_header, and the size of the header
+    fn header(bytes: &[u8]) -> (Vec<String>, usize) {
+        let end = b"end_header\n";
+        let size = bytes.windows(end.len()).position(|w| w == end).unwrap() + end.len();
+        let text = String::from_utf8(bytes[..size].to_vec()).unwrap();
+        (text.lines().map(str::to_owned).collect(), size)
+    }
+
+    #[test]
+    fn header_lists_the_written_properties() {
+        let img = RgbaImage::from_fn(2, 2, |x, y| Rgba([x as u8 * 255, y as u8 * 255, 0, 255]));
+        let depth = DepthMapBuilder::new()
+            .layers(2)
+            .build(&DynamicImage::ImageRgba8(img.clone()));
+        let bytes = export(
+            &PlyExporter {
+                ascii: true,
+                texture: Some(&img),
+                layers: Some(&depth),
+                points: false,
+            },
+            "shadowpuppet-header.ply",
+        );
+        let (lines, _) = header(&bytes);
+
+        let properties: Vec<&str> = lines
+            .iter()
+            .filter_map(|line| line.strip_prefix("property "))
+            .collect();
+        assert_eq!(
+            properties,
+            [
+                "float x",
+                "float y",
+                "float z",
+                "uchar red",
+                "uchar green",
+                "uchar blue",
+                "uchar layer",
+                "list uchar int vertex_indices"
+            ]
+        );
+        assert!(lines.contains(&"element vertex 4".to_owned()));
+        assert!(lines.contains(&"element face 2".to_owned()));
+
+        // Each vertex row has a value per property, then come the faces
+        let text = String::from_utf8(bytes).unwrap();
+        let body: Vec<&str> = text.lines().skip(lines.len()).collect();
+        assert_eq!(body.len(), 6);
+        assert!(body[..4].iter().all(|row| row.split(' ').count() == 7));
+        assert_eq!(body[4..], ["3 0 1 2", "3 0 2 3"]);
+    }
+
+    #[test]
+    fn point_cloud_has_no_faces() {
+        let bytes = export(
+            &PlyExporter {
+                points: true,
+                ..Default::default()
+            },
+            "shadowpuppet-points.ply",
+        );
+        let (lines, size) = header(&bytes);
+
+        assert!(lines.contains(&"format binary_little_endian 1.0".to_owned()));
+        assert!(!lines.iter().any(|line| line.starts_with("element face")));
+        assert_eq!(
+            lines
+                .iter()
+                .filter(|line| line.starts_with("property"))
+                .count(),
+            3
+        );
+        assert_eq!(bytes.len(), size + 4 * 12);
+    }
+}
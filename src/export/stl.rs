@@ -0,0 +1,122 @@
+use super::Exporter;
+use crate::mesh::Mesh;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Binary or ASCII .stl with per-facet normals
+pub struct StlExporter {
+    pub ascii: bool,
+}
+
+impl Exporter for StlExporter {
+    fn export(&self, mesh: &Mesh, path: &Path) -> std::io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+
+        if self.ascii {
+            write_ascii(&mut file, mesh)?;
+        } else {
+            write_binary(&mut file, mesh)?;
+        }
+
+        file.flush()
+    }
+}
+
+fn write_binary(file: &mut impl Write, mesh: &Mesh) -> std::io::Result<()> {
+    let count = u32::try_from(mesh.triangles.len()).map_err(|_| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "too many triangles for binary STL",
+        )
+    })?;
+
+    // 80-byte header, which must not start with "solid" or readers take it for ASCII
+    let mut header = [b' '; 80];
+    let title = b"Shadowpuppet relief";
+    header[..title.len()].copy_from_slice(title);
+    file.write_all(&header)?;
+    file.write_all(&count.to_le_bytes())?;
+
+    for &triangle in &mesh.triangles {
+        let normal = mesh.facet_normal(triangle);
+        for value in normal {
+            file.write_all(&value.to_le_bytes())?;
+        }
+        for index in triangle {
+            for value in mesh.positions[index as usize] {
+                file.write_all(&value.to_le_bytes())?;
+            }
+        }
+        // Attribute byte count, unused
+        file.write_all(&0u16.to_le_bytes())?;
+    }
+
+    Ok(())
+}
+
+fn write_ascii(file: &mut impl Write, mesh: &Mesh) -> std::io::Result<()> {
+    writeln!(file, "solid shadowpuppet")?;
+
+    for &triangle in &mesh.triangles {
+        let [nx, ny, nz] = mesh.facet_normal(triangle);
+        writeln!(file, "  facet normal {:e} {:e} {:e}", nx, ny, nz)?;
+        writeln!(file, "    outer loop")?;
+        for index in triangle {
+            let [x, y, z] = mesh.positions[index as usize];
+            writeln!(file, "      vertex {:e} {:e} {:e}", x, y, z)?;
+        }
+        writeln!(file, "    endloop")?;
+        writeln!(file, "  endfacet")?;
+    }
+
+    writeln!(file, "endsolid shadowpuppet")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Unit square split into two triangles
+    fn square() -> Mesh {
+        Mesh {
+            positions: vec![
+                [0.0, 0.0, 0.0],
+                [1.0, 0.0, 0.0],
+                [1.0, 1.0, 0.0],
+                [0.0, 1.0, 0.0],
+            ],
+            uvs: vec![[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]],
+            triangles: vec![[0, 1, 2], [0, 2, 3]],
+            layers: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn binary_stl_has_a_record_per_triangle() {
+        let mut bytes = Vec::new();
+        write_binary(&mut bytes, &square()).unwrap();
+
+        assert_eq!(bytes.len(), 84 + 50 * 2);
+        assert!(!bytes.starts_with(b"solid"));
+        assert_eq!(u32::from_le_bytes(bytes[80..84].try_into().unwrap()), 2);
+        // The first facet faces up
+        let normal: Vec<f32> = bytes[84..96]
+            .chunks(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        assert_eq!(normal, [0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn ascii_stl_has_a_facet_per_triangle() {
+        let mut bytes = Vec::new();
+        write_ascii(&mut bytes, &square()).unwrap();
+        let text = String::from_utf8(bytes).unwrap();
+
+        assert!(text.starts_with("solid shadowpuppet\n"));
+        assert!(text.ends_with("endsolid shadowpuppet\n"));
+        assert_eq!(text.matches("facet normal").count(), 2);
+        assert_eq!(text.matches("vertex").count(), 6);
+    }
+}
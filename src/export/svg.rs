@@ -0,0 +1,64 @@
+use crate::stencil::{Sheet, Stencils};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Writes shadow box stencils as cut paths for laser cutters and cutting
+/// plotters, either as one Inkscape layer per sheet or one file per sheet
+pub struct SvgExporter {
+    pub layered: bool,
+}
+
+impl SvgExporter {
+    /// Save the stencils; separate sheets go next to `path` as `<stem>-01.svg`
+    /// and so on. Returns the files written.
+    pub fn export(&self, stencils: &Stencils, path: &Path) -> std::io::Result<Vec<PathBuf>> {
+        if self.layered {
+            write_svg(stencils, &stencils.sheets, path)?;
+            return Ok(vec![path.to_path_buf()]);
+        }
+
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let mut paths = Vec::with_capacity(stencils.sheets.len());
+        for sheet in &stencils.sheets {
+            let sheet_path = path.with_file_name(format!("{}-{:02}.svg", stem, sheet.level + 1));
+            write_svg(stencils, std::slice::from_ref(sheet), &sheet_path)?;
+            paths.push(sheet_path);
+        }
+        Ok(paths)
+    }
+}
+
+fn write_svg(stencils: &Stencils, sheets: &[Sheet], path: &Path) -> std::io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+
+    writeln!(file, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        file,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:inkscape="http://www.inkscape.org/namespaces/inkscape" width="{w}mm" height="{h}mm" viewBox="0 0 {w} {h}">"#,
+        w = stencils.width_mm,
+        h = stencils.height_mm
+    )?;
+
+    for sheet in sheets {
+        writeln!(
+            file,
+            r##"  <g id="layer-{n:02}" inkscape:groupmode="layer" inkscape:label="Layer {n}" fill="none" stroke="#ff0000" stroke-width="0.1">"##,
+            n = sheet.level + 1
+        )?;
+        // Holes are cut out by the even-odd rule
+        write!(file, r#"    <path fill-rule="evenodd" d=""#)?;
+        for outline in &sheet.outlines {
+            for (i, [x, y]) in outline.iter().enumerate() {
+                let command = if i == 0 { 'M' } else { 'L' };
+                write!(file, "{}{:.3} {:.3} ", command, x, y)?;
+            }
+            write!(file, "Z ")?;
+        }
+        writeln!(file, r#""/>"#)?;
+        writeln!(file, "  </g>")?;
+    }
+
+    writeln!(file, "</svg>")?;
+    file.flush()
+}
@@ -0,0 +1,260 @@
+use super::Exporter;
+use crate::mesh::Mesh;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use zip::ZipWriter;
+use zip::write::SimpleFileOptions;
+
+const CONTENT_TYPES: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types">
+  <Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/>
+  <Default Extension="model" ContentType="application/vnd.ms-package.3dmanufacturing-3dmodel+xml"/>
+</Types>
+"#;
+
+const RELATIONSHIPS: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">
+  <Relationship Target="/3D/3dmodel.model" Id="rel0" Type="http://schemas.microsoft.com/3dmanufacturing/2013/01/3dmodel"/>
+</Relationships>
+"#;
+
+/// 3MF package in millimetres. A mesh split into layers becomes one object per
+/// layer, grouped into a single assembly, each with its own coloured material
+/// so multi-material slicers can give it an extruder.
+#[derive(Default)]
+pub struct ThreeMfExporter {
+    /// Colour of each depth layer; layers without one get a gray ramp
+    pub colors: Vec<[u8; 3]>,
+}
+
+impl Exporter for ThreeMfExporter {
+    fn export(&self, mesh: &Mesh, path: &Path) -> std::io::Result<()> {
+        let groups = layer_groups(mesh);
+        if groups.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "the mesh has no faces to write",
+            ));
+        }
+
+        let mut zip = ZipWriter::new(BufWriter::new(File::create(path)?));
+        let options = SimpleFileOptions::default();
+
+        zip.start_file("[Content_Types].xml", options)?;
+        zip.write_all(CONTENT_TYPES.as_bytes())?;
+        zip.start_file("_rels/.rels", options)?;
+        zip.write_all(RELATIONSHIPS.as_bytes())?;
+        zip.start_file("3D/3dmodel.model", options)?;
+        self.write_model(&mut zip, mesh, &groups)?;
+
+        zip.finish()?.flush()
+    }
+}
+
+impl ThreeMfExporter {
+    fn write_model(
+        &self,
+        file: &mut impl Write,
+        mesh: &Mesh,
+        groups: &BTreeMap<u8, Vec<[u32; 3]>>,
+    ) -> std::io::Result<()> {
+        let split = !mesh.layers.is_empty();
+        let top = groups.keys().next_back().copied().unwrap_or(0).max(1);
+
+        writeln!(file, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            file,
+            r#"<model unit="millimeter" xml:lang="en-US" xmlns="http://schemas.microsoft.com/3dmanufacturing/core/2015/02">"#
+        )?;
+        writeln!(
+            file,
+            r#"  <metadata name="Application">Shadowpuppet</metadata>"#
+        )?;
+        writeln!(file, "  <resources>")?;
+
+        if split {
+            writeln!(file, r#"    <basematerials id="1">"#)?;
+            for &level in groups.keys() {
+                let [r, g, b] = self
+                    .colors
+                    .get(level as usize)
+                    .copied()
+                    .unwrap_or_else(|| [(level as f32 / top as f32 * 255.0).round() as u8; 3]);
+                writeln!(
+                    file,
+                    r#"      <base name="Layer {}" displaycolor="{}"/>"#,
+                    level + 1,
+                    format_args!("#{:02X}{:02X}{:02X}FF", r, g, b)
+                )?;
+            }
+            writeln!(file, "    </basematerials>")?;
+        }
+
+        // Object ids start after the material group
+        let mut ids = Vec::with_capacity(groups.len());
+        for (index, (level, triangles)) in groups.iter().enumerate() {
+            let id = index + 2;
+            ids.push(id);
+            if split {
+                writeln!(
+                    file,
+                    r#"    <object id="{}" name="Layer {}" type="model" pid="1" pindex="{}">"#,
+                    id,
+                    level + 1,
+                    index
+                )?;
+            } else {
+                writeln!(
+                    file,
+                    r#"    <object id="{}" name="Relief" type="model">"#,
+                    id
+                )?;
+            }
+            write_mesh(file, mesh, triangles)?;
+            writeln!(file, "    </object>")?;
+        }
+
+        // Keep the layers together as parts of one object
+        let item = if split {
+            let id = ids.len() + 2;
+            writeln!(
+                file,
+                r#"    <object id="{}" name="Relief" type="model">"#,
+                id
+            )?;
+            writeln!(file, "      <components>")?;
+            for part in &ids {
+                writeln!(file, r#"        <component objectid="{}"/>"#, part)?;
+            }
+            writeln!(file, "      </components>")?;
+            writeln!(file, "    </object>")?;
+            id
+        } else {
+            ids.first().copied().unwrap_or(2)
+        };
+
+        writeln!(file, "  </resources>")?;
+        writeln!(file, "  <build>")?;
+        writeln!(file, r#"    <item objectid="{}"/>"#, item)?;
+        writeln!(file, "  </build>")?;
+        writeln!(file, "</model>")?;
+        Ok(())
+    }
+}
+
+// Triangles grouped by layer; an unsplit mesh is a single group. Layers with
+// nothing but degenerate triangles are left out, as slicers reject objects
+// without volume.
+fn layer_groups(mesh: &Mesh) -> BTreeMap<u8, Vec<[u32; 3]>> {
+    let mut groups: BTreeMap<u8, Vec<[u32; 3]>> = BTreeMap::new();
+    for (i, &triangle) in mesh.triangles.iter().enumerate() {
+        let level = mesh.layers.get(i).copied().unwrap_or(0);
+        groups.entry(level).or_default().push(triangle);
+    }
+    groups.retain(|_, triangles| {
+        triangles
+            .iter()
+            .any(|&triangle| mesh.facet_normal(triangle) != [0.0; 3])
+    });
+    groups
+}
+
+// Write the vertices used by `triangles`, renumbered from zero, and the triangles
+fn write_mesh(file: &mut impl Write, mesh: &Mesh, triangles: &[[u32; 3]]) -> std::io::Result<()> {
+    let mut indices = vec![u32::MAX; mesh.positions.len()];
+    let mut used = Vec::new();
+    for &vertex in triangles.iter().flatten() {
+        if indices[vertex as usize] == u32::MAX {
+            indices[vertex as usize] = used.len() as u32;
+            used.push(vertex);
+        }
+    }
+
+    writeln!(file, "      <mesh>")?;
+    writeln!(file, "        <vertices>")?;
+    for &vertex in &used {
+        let [x, y, z] = mesh.positions[vertex as usize];
+        writeln!(file, r#"          <vertex x="{}" y="{}" z="{}"/>"#, x, y, z)?;
+    }
+    writeln!(file, "        </vertices>")?;
+    writeln!(file, "        <triangles>")?;
+    for triangle in triangles {
+        let [v1, v2, v3] = triangle.map(|vertex| indices[vertex as usize]);
+        writeln!(
+            file,
+            r#"          <triangle v1="{}" v2="{}" v3="{}"/>"#,
+            v1, v2, v3
+        )?;
+    }
+    writeln!(file, "        </triangles>")?;
+    writeln!(file, "      </mesh>")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Two unit squares side by side, then a triangle with no area
+    fn squares() -> Mesh {
+        let positions: Vec<[f32; 3]> = (0..3)
+            .flat_map(|x| [[x as f32, 0.0, 0.0], [x as f32, 1.0, 0.0]])
+            .collect();
+        Mesh {
+            uvs: vec![[0.0, 0.0]; positions.len()],
+            positions,
+            triangles: vec![[0, 2, 3], [0, 3, 1], [2, 4, 5], [2, 5, 3], [0, 2, 4]],
+            layers: Vec::new(),
+        }
+    }
+
+    fn model(mesh: &Mesh) -> String {
+        let mut bytes = Vec::new();
+        ThreeMfExporter::default()
+            .write_model(&mut bytes, mesh, &layer_groups(mesh))
+            .unwrap();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn split_mesh_has_an_object_per_layer() {
+        let mut mesh = squares();
+        mesh.layers = vec![0, 0, 1, 1, 2];
+        let model = model(&mesh);
+
+        // The degenerate layer is left out
+        assert_eq!(model.matches("<object ").count(), 3);
+        assert_eq!(model.matches(r#"name="Layer "#).count(), 4);
+        assert!(
+            model.contains(r#"<object id="2" name="Layer 1" type="model" pid="1" pindex="0">"#)
+        );
+        assert!(
+            model.contains(r#"<object id="3" name="Layer 2" type="model" pid="1" pindex="1">"#)
+        );
+        assert_eq!(model.matches("<component ").count(), 2);
+        assert!(model.contains(r#"<item objectid="4"/>"#));
+    }
+
+    #[test]
+    fn unsplit_mesh_is_one_object() {
+        let model = model(&squares());
+
+        assert_eq!(model.matches("<object ").count(), 1);
+        assert!(!model.contains("<basematerials"));
+        assert_eq!(model.matches("<vertex ").count(), 6);
+        assert_eq!(model.matches("<triangle ").count(), 5);
+        assert!(model.contains(r#"<item objectid="2"/>"#));
+    }
+
+    #[test]
+    fn empty_mesh_is_refused() {
+        let path = std::env::temp_dir().join("shadowpuppet-empty.3mf");
+        let error = ThreeMfExporter::default()
+            .export(&Mesh::default(), &path)
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(!path.exists());
+    }
+}
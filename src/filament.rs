@@ -0,0 +1,194 @@
+use crate::mesh::Dimensions;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// One spool of filament
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Filament {
+    pub color: [u8; 3],
+    /// Thickness through which the filament hides what is printed below it,
+    /// the "TD" value published for HueForge
+    pub transmission_mm: f32,
+}
+
+impl Filament {
+    /// Colour as `#rrggbb`
+    pub fn hex(&self) -> String {
+        let [r, g, b] = self.color;
+        format!("#{:02x}{:02x}{:02x}", r, g, b)
+    }
+}
+
+/// Filament printed at each depth layer, from the bottom up. The lowest layer's
+/// filament also fills the base below it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FilamentPlan {
+    pub filaments: Vec<Filament>,
+    /// Slicer layer height, for numbering the layers where swaps happen
+    pub layer_height_mm: f32,
+}
+
+/// Filament change at the start of a printed layer
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Swap {
+    pub z_mm: f32,
+    /// Printed layer, counting from 1
+    pub layer: u32,
+    pub filament: Filament,
+}
+
+impl FilamentPlan {
+    /// Black base fading to white at the top, a usual start for grayscale prints
+    pub fn grayscale(layers: u8) -> Self {
+        let mut plan = FilamentPlan {
+            filaments: Vec::new(),
+            layer_height_mm: 0.08,
+        };
+        plan.resize(layers);
+        plan
+    }
+
+    /// Match the number of depth layers, keeping existing assignments and
+    /// filling new layers with a gray ramp
+    pub fn resize(&mut self, layers: u8) {
+        let layers = layers.max(2) as usize;
+        let steps = (layers - 1) as f32;
+        for level in self.filaments.len()..layers {
+            let v = (level as f32 / steps * 255.0).round() as u8;
+            self.filaments.push(Filament {
+                color: [v; 3],
+                transmission_mm: if level == 0 { 0.6 } else { 2.0 },
+            });
+        }
+        self.filaments.truncate(layers);
+    }
+
+    /// Predicted colour of each layer seen from above. Each run of one filament
+    /// covers what lies below it in proportion to its thickness over its
+    /// transmission distance.
+    pub fn colors(&self, dimensions: &Dimensions) -> Vec<[u8; 3]> {
+        let layers = self.filaments.len().min(u8::MAX as usize) as u8;
+        let mut colors: Vec<[u8; 3]> = Vec::with_capacity(self.filaments.len());
+        // The base is taken to be opaque
+        let mut below = self
+            .filaments
+            .first()
+            .map_or([0.0; 3], |f| f.color.map(|c| c as f32));
+        let mut run_start = 0.0;
+
+        for (level, filament) in self.filaments.iter().enumerate() {
+            if level > 0 && *filament != self.filaments[level - 1] {
+                below = colors[level - 1].map(|c| c as f32);
+                run_start = dimensions.layer_z(level as u8 - 1, layers);
+            }
+            let thickness = dimensions.layer_z(level as u8, layers) - run_start;
+            let cover = (thickness / filament.transmission_mm.max(0.01)).clamp(0.0, 1.0);
+            let color = filament.color.map(|c| c as f32);
+            colors.push(
+                [0, 1, 2].map(|i| (below[i] * (1.0 - cover) + color[i] * cover).round() as u8),
+            );
+        }
+
+        colors
+    }
+
+    /// Heights where the filament changes, starting with the first one at z = 0
+    pub fn swaps(&self, dimensions: &Dimensions) -> Vec<Swap> {
+        let layers = self.filaments.len().min(u8::MAX as usize) as u8;
+        let layer_height = self.layer_height_mm.max(0.01);
+        self.filaments
+            .iter()
+            .enumerate()
+            .filter(|&(level, filament)| level == 0 || *filament != self.filaments[level - 1])
+            .map(|(level, &filament)| {
+                let z_mm = match level {
+                    0 => 0.0,
+                    _ => dimensions.layer_z(level as u8 - 1, layers),
+                };
+                Swap {
+                    z_mm,
+                    layer: (z_mm / layer_height).round() as u32 + 1,
+                    filament,
+                }
+            })
+            .collect()
+    }
+
+    /// Where the schedule for the mesh at `mesh_path` goes, next to it
+    pub fn schedule_path(mesh_path: &Path) -> PathBuf {
+        mesh_path.with_extension("swaps.txt")
+    }
+
+    /// Write the swap schedule as a plain text table
+    pub fn write_schedule(&self, dimensions: &Dimensions, path: &Path) -> std::io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+
+        writeln!(file, "Filament swap schedule")?;
+        writeln!(file, "Layer height: {} mm", self.layer_height_mm)?;
+        writeln!(file)?;
+        writeln!(
+            file,
+            "{:>10}  {:>6}  {:<7}  {:>7}",
+            "Z (mm)", "Layer", "Colour", "TD (mm)"
+        )?;
+        for swap in self.swaps(dimensions) {
+            writeln!(
+                file,
+                "{:>10.2}  {:>6}  {:<7}  {:>7.2}",
+                swap.z_mm,
+                swap.layer,
+                swap.filament.hex(),
+                swap.filament.transmission_mm
+            )?;
+        }
+
+        file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filament(color: [u8; 3], transmission_mm: f32) -> Filament {
+        Filament {
+            color,
+            transmission_mm,
+        }
+    }
+
+    #[test]
+    fn swaps_sit_on_the_layer_tops_below_each_change() {
+        let (red, blue) = (filament([255, 0, 0], 1.0), filament([0, 0, 255], 1.0));
+        let plan = FilamentPlan {
+            filaments: vec![red, blue, blue, red],
+            layer_height_mm: 0.2,
+        };
+        let dimensions = Dimensions::default();
+
+        let swaps = plan.swaps(&dimensions);
+        let z: Vec<f32> = swaps.iter().map(|swap| swap.z_mm).collect();
+        assert_eq!(z, [0.0, dimensions.layer_z(0, 4), dimensions.layer_z(2, 4)]);
+        let layers: Vec<u32> = swaps.iter().map(|swap| swap.layer).collect();
+        assert_eq!(layers, [1, 6, 16]);
+        assert_eq!(swaps[1].filament, blue);
+        assert_eq!(swaps[2].filament, red);
+    }
+
+    #[test]
+    fn colours_blend_each_run_over_the_one_below() {
+        let (black, white) = (filament([0; 3], 0.6), filament([255; 3], 6.0));
+        let plan = FilamentPlan {
+            filaments: vec![black, white],
+            layer_height_mm: 0.08,
+        };
+        let dimensions = Dimensions::default();
+
+        // The white run is as thick as the relief, half its transmission distance
+        let cover = (dimensions.layer_z(1, 2) - dimensions.layer_z(0, 2)) / 6.0;
+        let gray = (255.0 * cover).round() as u8;
+        assert_eq!(plan.colors(&dimensions), [[0; 3], [gray; 3]]);
+        assert_eq!(gray, 128);
+    }
+}
@@ -0,0 +1,265 @@
+/// Smoothing or sharpening applied to depths before they're quantized, to keep
+/// photo noise from turning into speckled layers
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FilterKind {
+    #[default]
+    None,
+    /// Gaussian blur, softening everything alike
+    Gaussian,
+    /// Edge-preserving blur that only averages similar depths
+    Bilateral,
+    /// Median of the neighbourhood, removing isolated specks
+    Median,
+    /// Unsharp mask, exaggerating detail
+    Sharpen,
+}
+
+impl FilterKind {
+    pub const ALL: [FilterKind; 5] = [
+        FilterKind::None,
+        FilterKind::Gaussian,
+        FilterKind::Bilateral,
+        FilterKind::Median,
+        FilterKind::Sharpen,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            FilterKind::None => "None",
+            FilterKind::Gaussian => "Gaussian blur",
+            FilterKind::Bilateral => "Bilateral (edge-preserving)",
+            FilterKind::Median => "Median (despeckle)",
+            FilterKind::Sharpen => "Sharpen (unsharp mask)",
+        }
+    }
+}
+
+/// Filter stage of a [`DepthMapBuilder`](crate::DepthMapBuilder)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Filter {
+    pub kind: FilterKind,
+    /// Size of the neighbourhood in pixels; the standard deviation of the
+    /// Gaussian, bilateral and sharpen filters and the reach of the median
+    pub radius_px: f32,
+    /// Largest depth difference, in 0.0..=1.0, the bilateral filter still
+    /// smooths across
+    pub range: f32,
+    /// How strongly sharpening pushes detail away from the blurred image
+    pub amount: f32,
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Filter {
+            kind: FilterKind::None,
+            radius_px: 1.5,
+            range: 0.1,
+            amount: 1.0,
+        }
+    }
+}
+
+impl Filter {
+    /// Filter row-major depths in place. Pixels outside `mask` neither change
+    /// nor contribute to their neighbours.
+    pub fn apply(&self, values: &mut [f32], width: usize, height: usize, mask: Option<&[bool]>) {
+        if self.radius_px <= 0.0 || width == 0 || height == 0 {
+            return;
+        }
+        let filtered = match self.kind {
+            FilterKind::None => return,
+            FilterKind::Gaussian => gaussian(values, width, height, mask, self.radius_px),
+            FilterKind::Bilateral => {
+                bilateral(values, width, height, mask, self.radius_px, self.range)
+            }
+            FilterKind::Median => median(values, width, height, mask, self.radius_px),
+            FilterKind::Sharpen => {
+                let blurred = gaussian(values, width, height, mask, self.radius_px);
+                values
+                    .iter()
+                    .zip(blurred)
+                    .map(|(&value, blur)| value + self.amount * (value - blur))
+                    .collect()
+            }
+        };
+
+        for (i, (value, filtered)) in values.iter_mut().zip(filtered).enumerate() {
+            if mask.is_none_or(|mask| mask[i]) {
+                *value = filtered.clamp(0.0, 1.0);
+            }
+        }
+    }
+}
+
+// Separable blur, normalized by the weight of the opaque pixels it covers
+fn gaussian(
+    values: &[f32],
+    width: usize,
+    height: usize,
+    mask: Option<&[bool]>,
+    sigma: f32,
+) -> Vec<f32> {
+    let reach = (sigma * 3.0).ceil() as isize;
+    let kernel: Vec<f32> = (-reach..=reach)
+        .map(|d| (-(d * d) as f32 / (2.0 * sigma * sigma)).exp())
+        .collect();
+
+    let weight: Vec<f32> = (0..values.len())
+        .map(|i| {
+            if mask.is_none_or(|mask| mask[i]) {
+                1.0
+            } else {
+                0.0
+            }
+        })
+        .collect();
+    let weighted: Vec<f32> = values.iter().zip(&weight).map(|(v, w)| v * w).collect();
+
+    // Blur the weighted values and the weights alike, first along rows then columns
+    let pass = |source: &[f32], step: (isize, isize)| -> Vec<f32> {
+        let mut out = vec![0.0; source.len()];
+        for y in 0..height as isize {
+            for x in 0..width as isize {
+                let mut sum = 0.0;
+                for (k, d) in (-reach..=reach).enumerate() {
+                    let (sx, sy) = (x + d * step.0, y + d * step.1);
+                    if sx >= 0 && sy >= 0 && sx < width as isize && sy < height as isize {
+                        sum += kernel[k] * source[sy as usize * width + sx as usize];
+                    }
+                }
+                out[y as usize * width + x as usize] = sum;
+            }
+        }
+        out
+    };
+    let values = pass(&pass(&weighted, (1, 0)), (0, 1));
+    let weights = pass(&pass(&weight, (1, 0)), (0, 1));
+
+    values
+        .into_iter()
+        .zip(weights)
+        .map(|(value, weight)| if weight > 0.0 { value / weight } else { 0.0 })
+        .collect()
+}
+
+fn bilateral(
+    values: &[f32],
+    width: usize,
+    height: usize,
+    mask: Option<&[bool]>,
+    sigma: f32,
+    range: f32,
+) -> Vec<f32> {
+    let reach = (sigma * 2.0).ceil() as isize;
+    let range = range.max(f32::EPSILON);
+    let mut out = values.to_vec();
+
+    for y in 0..height as isize {
+        for x in 0..width as isize {
+            let center = values[y as usize * width + x as usize];
+            let (mut sum, mut total) = (0.0, 0.0);
+            for sy in (y - reach).max(0)..=(y + reach).min(height as isize - 1) {
+                for sx in (x - reach).max(0)..=(x + reach).min(width as isize - 1) {
+                    let i = sy as usize * width + sx as usize;
+                    if !mask.is_none_or(|mask| mask[i]) {
+                        continue;
+                    }
+                    let (dx, dy) = ((sx - x) as f32, (sy - y) as f32);
+                    let difference = values[i] - center;
+                    let weight = (-(dx * dx + dy * dy) / (2.0 * sigma * sigma)
+                        - difference * difference / (2.0 * range * range))
+                        .exp();
+                    sum += weight * values[i];
+                    total += weight;
+                }
+            }
+            if total > 0.0 {
+                out[y as usize * width + x as usize] = sum / total;
+            }
+        }
+    }
+    out
+}
+
+fn median(
+    values: &[f32],
+    width: usize,
+    height: usize,
+    mask: Option<&[bool]>,
+    radius: f32,
+) -> Vec<f32> {
+    let reach = radius.round().max(1.0) as isize;
+    let mut out = values.to_vec();
+    let mut window = Vec::new();
+
+    for y in 0..height as isize {
+        for x in 0..width as isize {
+            window.clear();
+            for sy in (y - reach).max(0)..=(y + reach).min(height as isize - 1) {
+                for sx in (x - reach).max(0)..=(x + reach).min(width as isize - 1) {
+                    let i = sy as usize * width + sx as usize;
+                    if mask.is_none_or(|mask| mask[i]) {
+                        window.push(values[i]);
+                    }
+                }
+            }
+            if !window.is_empty() {
+                let middle = window.len() / 2;
+                let (_, &mut value, _) = window.select_nth_unstable_by(middle, f32::total_cmp);
+                out[y as usize * width + x as usize] = value;
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SMOOTHING: [FilterKind; 4] = [
+        FilterKind::Gaussian,
+        FilterKind::Bilateral,
+        FilterKind::Median,
+        FilterKind::Sharpen,
+    ];
+
+    fn filter(kind: FilterKind) -> Filter {
+        Filter {
+            kind,
+            ..Filter::default()
+        }
+    }
+
+    #[test]
+    fn constant_depths_are_unchanged() {
+        for kind in SMOOTHING {
+            let mut values = vec![0.4; 7 * 5];
+            filter(kind).apply(&mut values, 7, 5, None);
+            assert!(
+                values.iter().all(|&v| (v - 0.4).abs() < 1e-6),
+                "{kind:?} gave {values:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn masked_pixels_are_left_out() {
+        // Diagonal stripes of cut-out pixels holding a depth far from the rest
+        let (width, height) = (7, 5);
+        let mask: Vec<bool> = (0..width * height)
+            .map(|i| (i % width + i / width) % 3 != 0)
+            .collect();
+        for kind in SMOOTHING {
+            let mut values: Vec<f32> = mask.iter().map(|&m| if m { 0.4 } else { 1.0 }).collect();
+            filter(kind).apply(&mut values, width, height, Some(&mask));
+            for (i, &value) in values.iter().enumerate() {
+                let expected = if mask[i] { 0.4 } else { 1.0 };
+                assert!(
+                    (value - expected).abs() < 1e-6,
+                    "{kind:?} changed pixel {i} to {value}"
+                );
+            }
+        }
+    }
+}
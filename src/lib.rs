@@ -0,0 +1,38 @@
+//! Turn bitmap photos into depth-mapped 3D meshes.
+//!
+//! An image is first quantized into a [`DepthMap`] by a [`DepthMapBuilder`],
+//! shaped into a [`Mesh`] according to [`MeshOptions`], and written out by an
+//! [`Exporter`]:
+//!
+//! ```no_run
+//! use shadowpuppet::{DepthMapBuilder, ExportFormat, Mesh, MeshOptions};
+//!
+//! let img = image::open("photo.png")?;
+//! let depth = DepthMapBuilder::new().layers(12).build(&img);
+//! let mesh = Mesh::build(&depth, &MeshOptions::default());
+//! ExportFormat::Stl.exporter(None).export(&mesh, "relief.stl".as_ref())?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+pub mod depth;
+pub mod export;
+pub mod filament;
+pub mod filter;
+pub mod mesh;
+pub mod quantize;
+pub mod render;
+pub mod stencil;
+pub mod tone;
+
+pub use depth::{Channel, DepthMap, DepthMapBuilder};
+pub use export::{
+    ExportFormat, Exporter, GlbExporter, HeightmapExporter, ObjExporter, PlyExporter, SvgExporter,
+    ThreeMfExporter,
+};
+pub use filament::{Filament, FilamentPlan, Swap};
+pub use filter::{Filter, FilterKind};
+pub use mesh::{Curve, Dimensions, Lithophane, Mesh, MeshOptions, MeshStyle, Projection};
+pub use quantize::Quantizer;
+pub use render::Camera;
+pub use stencil::{Sheet, StencilOptions, Stencils};
+pub use tone::Tone;
@@ -4,24 +4,79 @@ use gio::SimpleAction;
 use gtk4::cairo;
 use gtk4::{gio, glib};
 use gtk4::{Application, DrawingArea, FileChooserAction};
-use image::{DynamicImage, GenericImageView, RgbImage};
+use image::DynamicImage;
 use std::cell::RefCell;
-use std::fs::File;
-use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 use std::rc::Rc;
 
-fn main() -> glib::ExitCode {
+use shadowpuppet::export::is_same_file;
+use shadowpuppet::{
+    Camera, Channel, Curve, DepthMap, DepthMapBuilder, ExportFormat, Exporter, FilamentPlan,
+    FilterKind, HeightmapExporter, Lithophane, Mesh, MeshOptions, MeshStyle, ObjExporter,
+    PlyExporter, Projection, Quantizer, StencilOptions, Stencils, SvgExporter, ThreeMfExporter,
+    Tone,
+};
+
+mod cli;
+
+// Save dialog filter for shadow box stencils, alongside the mesh formats
+const STENCIL_FILTER: &str = "SVG stencils";
+
+// Save dialog filters for grayscale heightmaps, with their extensions
+const HEIGHTMAP_FILTERS: [(&str, &str); 2] =
+    [("Heightmap (PNG)", "png"), ("Heightmap (TIFF)", "tiff")];
+
+// Units the minimum region for speckle cleanup can be given in
+const REGION_UNITS: [&str; 2] = ["Pixels", "Square millimetres"];
+const REGION_MM2: u32 = 1;
+
+// How close, in pixels, a click must be to pick up a tone curve point
+const CURVE_POINT_REACH: f64 = 10.0;
+// Space around the tone curve, so points at its ends can still be grabbed
+const CURVE_MARGIN: f64 = 8.0;
+
+// Longest side of the image the 3D preview is built from, so it rebuilds quickly
+const MODEL_PREVIEW_SIZE: u32 = 400;
+
+fn main() -> ExitCode {
+    // `shadowpuppet convert ...` runs headless instead of opening a window
+    if std::env::args().nth(1).as_deref() == Some("convert") {
+        return cli::run();
+    }
+
     let app = adw::Application::builder()
         .application_id("com.example.Shadowpuppet")
         .build();
 
     app.connect_activate(build_ui);
-    app.run()
+    app.run().into()
 }
 
 fn build_ui(app: &adw::Application) {
-    let img_data: Rc<RefCell<Option<RgbImage>>> = Rc::new(RefCell::new(None));
-    let num_layers = Rc::new(RefCell::new(8u8)); // default layers
+    // Kept at its own bit depth, so 16-bit sources give smooth depth
+    let img_data: Rc<RefCell<Option<DynamicImage>>> = Rc::new(RefCell::new(None));
+    // Where the image was loaded from, so exports never write over it
+    let image_path: Rc<RefCell<Option<PathBuf>>> = Rc::new(RefCell::new(None));
+    let depth_settings = Rc::new(RefCell::new(DepthMapBuilder::new()));
+    let export_texture = Rc::new(RefCell::new(true));
+    let mesh_options = Rc::new(RefCell::new(MeshOptions::default()));
+    let lithophane = Rc::new(RefCell::new(Lithophane::default()));
+    let lithophane_mode = Rc::new(RefCell::new(false));
+    let stencil_options = Rc::new(RefCell::new(StencilOptions::default()));
+    let separate_sheets = Rc::new(RefCell::new(false));
+    let ply_points = Rc::new(RefCell::new(false));
+    let ply_layers = Rc::new(RefCell::new(false));
+    let heightmap_16bit = Rc::new(RefCell::new(true));
+    let filament_plan = Rc::new(RefCell::new(FilamentPlan::grayscale(8)));
+    let filament_mode = Rc::new(RefCell::new(false));
+    // Set while one size row updates the other, so they don't feed back
+    let syncing_size = Rc::new(RefCell::new(false));
+    // The preview shows the rendered model instead of the quantized image
+    let view_3d = Rc::new(RefCell::new(false));
+    // Pixels speckle cleanup moved to another layer are tinted in the preview
+    let highlight_merged = Rc::new(RefCell::new(true));
+    let camera = Rc::new(RefCell::new(Camera::default()));
 
     // Create toast overlay for notifications
     let toast_overlay = adw::ToastOverlay::new();
@@ -31,16 +86,35 @@ fn build_ui(app: &adw::Application) {
 
     preview_area.set_size_request(300, 300);
 
+    // Histogram of depths with the layer boundaries over it, set up with the
+    // other layer settings below
+    let threshold_area = DrawingArea::builder().hexpand(true).build();
+
     // Cache for the processed image surface
     let cached_surface: Rc<RefCell<Option<cairo::ImageSurface>>> = Rc::new(RefCell::new(None));
-    let cached_layers: Rc<RefCell<u8>> = Rc::new(RefCell::new(8));
+    // Depth settings, in filament mode the predicted colour of each layer, and
+    // whether merged specks are highlighted
+    type PreviewSettings = (DepthMapBuilder, Option<Vec<[u8; 3]>>, bool);
+    let cached_settings: Rc<RefCell<Option<PreviewSettings>>> = Rc::new(RefCell::new(None));
+    // The 3D preview's mesh and the settings it was built with
+    let cached_model: Rc<RefCell<Option<((DepthMapBuilder, MeshOptions), Mesh)>>> =
+        Rc::new(RefCell::new(None));
 
     // Drawing function
     {
         let img_data = img_data.clone();
-        let num_layers = num_layers.clone();
+        let depth_settings = depth_settings.clone();
+        let mesh_options = mesh_options.clone();
+        let lithophane = lithophane.clone();
+        let lithophane_mode = lithophane_mode.clone();
+        let filament_plan = filament_plan.clone();
+        let filament_mode = filament_mode.clone();
         let cached_surface = cached_surface.clone();
-        let cached_layers = cached_layers.clone();
+        let cached_settings = cached_settings.clone();
+        let highlight_merged = highlight_merged.clone();
+        let view_3d = view_3d.clone();
+        let camera = camera.clone();
+        let cached_model = cached_model.clone();
 
         preview_area.set_draw_func(move |area, cr, width, height| {
             let theme_bg = area.style_context().lookup_color("window_bg_color");
@@ -57,10 +131,52 @@ fn build_ui(app: &adw::Application) {
             cr.paint().unwrap();
 
             if let Some(ref img) = *img_data.borrow() {
-                let current_layers = *num_layers.borrow();
+                if *view_3d.borrow() {
+                    let mut options = *mesh_options.borrow();
+                    options.lithophane = lithophane_mode.borrow().then(|| *lithophane.borrow());
+                    let settings = (depth_settings.borrow().clone(), options);
+
+                    let mut cached_model = cached_model.borrow_mut();
+                    if cached_model.as_ref().map(|(cached, _)| cached) != Some(&settings) {
+                        // Pitch follows the model's size, so a smaller copy keeps its shape
+                        let small = img.thumbnail(MODEL_PREVIEW_SIZE, MODEL_PREVIEW_SIZE);
+                        // Filters and speckle cleanup reach as far across the picture
+                        // as at full size
+                        let scale = small.width() as f32 / img.width() as f32;
+                        let mut builder = settings.0.clone();
+                        builder.filter.radius_px *= scale;
+                        builder.min_region_px =
+                            (builder.min_region_px as f32 * scale * scale).ceil() as usize;
+                        let depth = builder.build(&small);
+                        let mesh = Mesh::build(&depth, &settings.1);
+                        *cached_model = Some((settings, mesh));
+                    }
+
+                    let background = theme_bg
+                        .map(|color| {
+                            [color.red(), color.green(), color.blue()]
+                                .map(|v| (v * 255.0).round() as u8)
+                        })
+                        .unwrap_or([38; 3]);
+                    if let Some((_, ref mesh)) = *cached_model {
+                        draw_model(cr, mesh, &camera.borrow(), width, height, background);
+                    }
+                    return;
+                }
+
+                let settings = depth_settings.borrow().clone();
+                // Filament mode shows the colours the print should come out in
+                let palette = filament_mode.borrow().then(|| {
+                    filament_plan
+                        .borrow()
+                        .colors(&mesh_options.borrow().dimensions)
+                });
+                let settings = (settings, palette, *highlight_merged.borrow());
 
-                // Regenerate surface if layers changed or surface doesn't exist
-                if cached_surface.borrow().is_none() || *cached_layers.borrow() != current_layers {
+                // Regenerate surface if settings changed or surface doesn't exist
+                if cached_surface.borrow().is_none()
+                    || cached_settings.borrow().as_ref() != Some(&settings)
+                {
                     let img_width = img.width() as i32;
                     let img_height = img.height() as i32;
 
@@ -70,36 +186,44 @@ fn build_ui(app: &adw::Application) {
                             .unwrap();
 
                     {
-                        let scale_val = 255.0 / (current_layers as f32 - 1.0);
+                        let (builder, palette, highlight) = &settings;
+                        let depth = builder.build(img);
                         let stride = surface.stride() as usize;
-                        let width = img_width as usize;
-                        let height = img_height as usize;
 
                         let mut data = surface.data().unwrap();
-                        let pixels = img.as_raw();
 
-                        for y in 0..height {
+                        for y in 0..depth.height {
                             let row_offset = y * stride;
-                            let src_offset = y * width * 3;
-                            for x in 0..width {
-                                let si = src_offset + x * 3;
-                                let r = pixels[si] as f32;
-                                let g = pixels[si + 1] as f32;
-                                let b = pixels[si + 2] as f32;
-                                let gray = 0.299 * r + 0.587 * g + 0.114 * b;
-                                let quantized =
-                                    ((gray / scale_val).round() * scale_val).clamp(0.0, 255.0);
-                                let v = quantized as u8;
+                            for x in 0..depth.width {
+                                let v = depth.gray(x, y);
+                                let [r, g, b] = if !depth.opaque(x, y) {
+                                    // Cut-out pixels show a checkerboard, as transparency does
+                                    let light = (x / 8 + y / 8) % 2 == 0;
+                                    [if light { 0xcc } else { 0x99 }; 3]
+                                } else {
+                                    palette
+                                        .as_ref()
+                                        .and_then(|palette| palette.get(depth.level(x, y) as usize))
+                                        .copied()
+                                        .unwrap_or([v; 3])
+                                };
+                                // Merged specks are tinted magenta
+                                let [r, g, b] = if *highlight && depth.is_merged(x, y) {
+                                    [r / 2 + 0x80, g / 2, b / 2 + 0x80]
+                                } else {
+                                    [r, g, b]
+                                };
+                                // Cairo stores RGB24 pixels as little-endian BGRx
                                 let di = row_offset + x * 4;
-                                data[di] = v;
-                                data[di + 1] = v;
-                                data[di + 2] = v;
+                                data[di] = b;
+                                data[di + 1] = g;
+                                data[di + 2] = r;
                             }
                         }
                     }
 
                     *cached_surface.borrow_mut() = Some(surface);
-                    *cached_layers.borrow_mut() = current_layers;
+                    *cached_settings.borrow_mut() = Some(settings);
                 }
 
                 // Draw the cached surface with proper scaling and centering
@@ -170,12 +294,118 @@ fn build_ui(app: &adw::Application) {
     // Wrap preview in a frame for better visual separation
     let preview_frame = gtk4::Frame::builder().child(&preview_area).build();
 
+    // Switch the preview between the quantized image and the model it makes
+    let image_toggle = gtk4::ToggleButton::builder()
+        .label("Image")
+        .active(true)
+        .build();
+    let model_toggle = gtk4::ToggleButton::builder().label("3D").build();
+    model_toggle.set_group(Some(&image_toggle));
+
+    let view_switcher = gtk4::Box::builder()
+        .orientation(gtk4::Orientation::Horizontal)
+        .halign(gtk4::Align::Center)
+        .build();
+    view_switcher.add_css_class("linked");
+    view_switcher.append(&image_toggle);
+    view_switcher.append(&model_toggle);
+
+    let preview_box = gtk4::Box::builder()
+        .orientation(gtk4::Orientation::Vertical)
+        .spacing(6)
+        .build();
+    preview_box.append(&view_switcher);
+    preview_box.append(&preview_frame);
+
+    {
+        let view_3d = view_3d.clone();
+        let preview_area = preview_area.clone();
+        model_toggle.connect_toggled(move |button| {
+            *view_3d.borrow_mut() = button.is_active();
+            preview_area.queue_draw();
+        });
+    }
+
+    // In 3D, dragging orbits the model, the secondary button or Shift pans it,
+    // scrolling zooms and a double click brings back the starting view
+    {
+        let drag = gtk4::GestureDrag::builder().button(0).build();
+        let start = Rc::new(RefCell::new(Camera::default()));
+
+        {
+            let camera = camera.clone();
+            let start = start.clone();
+            drag.connect_drag_begin(move |_, _, _| {
+                *start.borrow_mut() = *camera.borrow();
+            });
+        }
+
+        {
+            let view_3d = view_3d.clone();
+            let camera = camera.clone();
+            let preview_area = preview_area.clone();
+            drag.connect_drag_update(move |gesture, dx, dy| {
+                if !*view_3d.borrow() {
+                    return;
+                }
+                let width = preview_area.width().max(1) as f32;
+                let height = preview_area.height().max(1) as f32;
+                let pan = gesture.current_button() == gtk4::gdk::BUTTON_SECONDARY
+                    || gesture
+                        .current_event_state()
+                        .contains(gtk4::gdk::ModifierType::SHIFT_MASK);
+
+                // Offsets are from where the drag began, so apply them to that view
+                let mut moved = *start.borrow();
+                if pan {
+                    moved.pan(dx as f32 / height, dy as f32 / height);
+                } else {
+                    moved.orbit(dx as f32 / width, dy as f32 / height);
+                }
+                *camera.borrow_mut() = moved;
+                preview_area.queue_draw();
+            });
+        }
+
+        preview_area.add_controller(drag);
+    }
+
+    {
+        let scroll = gtk4::EventControllerScroll::new(gtk4::EventControllerScrollFlags::VERTICAL);
+        let view_3d = view_3d.clone();
+        let camera = camera.clone();
+        let preview_area_for_scroll = preview_area.clone();
+        scroll.connect_scroll(move |_, _, dy| {
+            if !*view_3d.borrow() {
+                return glib::Propagation::Proceed;
+            }
+            camera.borrow_mut().zoom(1.1f32.powf(dy as f32));
+            preview_area_for_scroll.queue_draw();
+            glib::Propagation::Stop
+        });
+        preview_area.add_controller(scroll);
+    }
+
+    {
+        let click = gtk4::GestureClick::new();
+        let view_3d = view_3d.clone();
+        let camera = camera.clone();
+        let preview_area_for_click = preview_area.clone();
+        click.connect_pressed(move |_, presses, _, _| {
+            if presses == 2 && *view_3d.borrow() {
+                *camera.borrow_mut() = Camera::default();
+                preview_area_for_click.queue_draw();
+            }
+        });
+        preview_area.add_controller(click);
+    }
+
     // UI layout with modern Libadwaita widgets
     let open_button = gtk4::Button::from_icon_name("document-open-symbolic");
     open_button.set_tooltip_text(Some("Open Image"));
 
     let save_button = gtk4::Button::from_icon_name("document-save-symbolic");
-    save_button.set_tooltip_text(Some("Export as OBJ"));
+    save_button.set_tooltip_text(Some("Export Mesh"));
 
     // Create the WindowTitle
     let window_title = adw::WindowTitle::new("Shadowpuppet", "");
@@ -195,13 +425,13 @@ fn build_ui(app: &adw::Application) {
         .halign(gtk4::Align::Start)
         .build();
 
-    let slider = gtk4::Scale::with_range(gtk4::Orientation::Horizontal, 2.0, 64.0, 1.0);
+    let slider = gtk4::Scale::with_range(gtk4::Orientation::Horizontal, 2.0, 255.0, 1.0);
     slider.set_value(8.0);
     slider.set_draw_value(false);
     slider.set_hexpand(true);
 
     // Create a SpinButton for numeric entry
-    let spin_button = gtk4::SpinButton::with_range(2.0, 64.0, 1.0);
+    let spin_button = gtk4::SpinButton::with_range(2.0, 255.0, 1.0);
     spin_button.set_value(8.0);
     spin_button.set_digits(0);
     spin_button.set_width_chars(3);
@@ -244,23 +474,1238 @@ fn build_ui(app: &adw::Application) {
 
     // Connect spin button to slider
     {
+        let threshold_area = threshold_area.clone();
         let slider = slider.clone();
-        let num_layers_for_spin = num_layers.clone();
+        let depth_settings_for_spin = depth_settings.clone();
         let preview_area_for_spin = preview_area.clone();
         spin_button.connect_value_changed(move |s| {
             slider.set_value(s.value());
-            *num_layers_for_spin.borrow_mut() = s.value() as u8;
+            depth_settings_for_spin.borrow_mut().layers = s.value() as u8;
             preview_area_for_spin.queue_draw();
+            threshold_area.queue_draw();
         });
     }
 
     // Slider value changed handler
     {
-        let num_layers_for_slider = num_layers.clone();
+        let threshold_area = threshold_area.clone();
+        let depth_settings_for_slider = depth_settings.clone();
         let preview_area_for_slider = preview_area.clone();
         slider.connect_value_changed(move |s| {
-            *num_layers_for_slider.borrow_mut() = s.value() as u8;
+            depth_settings_for_slider.borrow_mut().layers = s.value() as u8;
             preview_area_for_slider.queue_draw();
+            threshold_area.queue_draw();
+        });
+    }
+
+    // Which pixel property becomes depth, and which way up
+    let channel_model = gtk4::StringList::new(&Channel::ALL.map(Channel::label));
+    let channel_row = adw::ComboRow::builder()
+        .title("Depth Source")
+        .subtitle("Pixel property mapped to height")
+        .model(&channel_model)
+        .build();
+
+    let invert_row = adw::SwitchRow::builder()
+        .title("Invert Depth")
+        .subtitle("Make darker areas higher, as for lithophanes")
+        .build();
+
+    // Full precision between layers, for 16-bit sources and smooth meshes
+    let continuous_row = adw::SwitchRow::builder()
+        .title("Continuous Depth")
+        .subtitle("Keep exact depths for smooth meshes, lithophanes and heightmaps")
+        .build();
+
+    preferences_group.add(&channel_row);
+    preferences_group.add(&invert_row);
+    preferences_group.add(&continuous_row);
+
+    // Transparent pixels leave holes, so the relief follows the image's outline
+    let cut_out_row = adw::SwitchRow::builder()
+        .title("Cut Out Transparency")
+        .subtitle("Leave transparent pixels out of the mesh")
+        .active(true)
+        .build();
+
+    preferences_group.add(&cut_out_row);
+
+    {
+        let threshold_area = threshold_area.clone();
+        let depth_settings = depth_settings.clone();
+        let preview_area = preview_area.clone();
+        cut_out_row.connect_active_notify(move |row| {
+            depth_settings.borrow_mut().cut_out = row.is_active();
+            preview_area.queue_draw();
+            threshold_area.queue_draw();
+        });
+    }
+
+    {
+        let threshold_area = threshold_area.clone();
+        let depth_settings = depth_settings.clone();
+        let preview_area = preview_area.clone();
+        continuous_row.connect_active_notify(move |row| {
+            depth_settings.borrow_mut().continuous = row.is_active();
+            preview_area.queue_draw();
+            threshold_area.queue_draw();
+        });
+    }
+
+    {
+        let threshold_area = threshold_area.clone();
+        let depth_settings = depth_settings.clone();
+        let preview_area = preview_area.clone();
+        channel_row.connect_selected_notify(move |row| {
+            if let Some(&channel) = Channel::ALL.get(row.selected() as usize) {
+                depth_settings.borrow_mut().channel = channel;
+                preview_area.queue_draw();
+                threshold_area.queue_draw();
+            }
+        });
+    }
+
+    {
+        let threshold_area = threshold_area.clone();
+        let depth_settings = depth_settings.clone();
+        let preview_area = preview_area.clone();
+        invert_row.connect_active_notify(move |row| {
+            depth_settings.borrow_mut().invert = row.is_active();
+            preview_area.queue_draw();
+            threshold_area.queue_draw();
+        });
+    }
+
+    // Smoothing before quantization keeps photo noise out of the layers
+    let filter_defaults = DepthMapBuilder::default().filter;
+
+    let filter_model = gtk4::StringList::new(&FilterKind::ALL.map(FilterKind::label));
+    let filter_row = adw::ComboRow::builder()
+        .title("Filter")
+        .subtitle("Smooth or sharpen the image before it is split into layers")
+        .model(&filter_model)
+        .build();
+
+    let filter_radius_row = adw::SpinRow::with_range(0.5, 20.0, 0.5);
+    filter_radius_row.set_title("Filter Radius (px)");
+    filter_radius_row.set_subtitle("Size of the neighbourhood each pixel is filtered over");
+    filter_radius_row.set_digits(1);
+    filter_radius_row.set_value(filter_defaults.radius_px as f64);
+    filter_radius_row.set_sensitive(false);
+
+    let filter_range_row = adw::SpinRow::with_range(0.01, 1.0, 0.01);
+    filter_range_row.set_title("Edge Threshold");
+    filter_range_row.set_subtitle("Depth difference the bilateral filter still smooths across");
+    filter_range_row.set_digits(2);
+    filter_range_row.set_value(filter_defaults.range as f64);
+    filter_range_row.set_sensitive(false);
+
+    let sharpen_amount_row = adw::SpinRow::with_range(0.1, 5.0, 0.1);
+    sharpen_amount_row.set_title("Sharpen Amount");
+    sharpen_amount_row.set_subtitle("How strongly detail is exaggerated");
+    sharpen_amount_row.set_digits(1);
+    sharpen_amount_row.set_value(filter_defaults.amount as f64);
+    sharpen_amount_row.set_sensitive(false);
+
+    preferences_group.add(&filter_row);
+    preferences_group.add(&filter_radius_row);
+    preferences_group.add(&filter_range_row);
+    preferences_group.add(&sharpen_amount_row);
+
+    {
+        let threshold_area = threshold_area.clone();
+        let depth_settings = depth_settings.clone();
+        let preview_area = preview_area.clone();
+        let filter_radius_row = filter_radius_row.clone();
+        let filter_range_row = filter_range_row.clone();
+        let sharpen_amount_row = sharpen_amount_row.clone();
+        filter_row.connect_selected_notify(move |row| {
+            if let Some(&kind) = FilterKind::ALL.get(row.selected() as usize) {
+                depth_settings.borrow_mut().filter.kind = kind;
+                filter_radius_row.set_sensitive(kind != FilterKind::None);
+                filter_range_row.set_sensitive(kind == FilterKind::Bilateral);
+                sharpen_amount_row.set_sensitive(kind == FilterKind::Sharpen);
+                preview_area.queue_draw();
+                threshold_area.queue_draw();
+            }
+        });
+    }
+
+    {
+        let threshold_area = threshold_area.clone();
+        let depth_settings = depth_settings.clone();
+        let preview_area = preview_area.clone();
+        filter_radius_row.connect_value_notify(move |row| {
+            depth_settings.borrow_mut().filter.radius_px = row.value() as f32;
+            preview_area.queue_draw();
+            threshold_area.queue_draw();
+        });
+    }
+
+    {
+        let threshold_area = threshold_area.clone();
+        let depth_settings = depth_settings.clone();
+        let preview_area = preview_area.clone();
+        filter_range_row.connect_value_notify(move |row| {
+            depth_settings.borrow_mut().filter.range = row.value() as f32;
+            preview_area.queue_draw();
+            threshold_area.queue_draw();
+        });
+    }
+
+    {
+        let threshold_area = threshold_area.clone();
+        let depth_settings = depth_settings.clone();
+        let preview_area = preview_area.clone();
+        sharpen_amount_row.connect_value_notify(move |row| {
+            depth_settings.borrow_mut().filter.amount = row.value() as f32;
+            preview_area.queue_draw();
+            threshold_area.queue_draw();
+        });
+    }
+
+    // Speckle cleanup merges islands too small to print into the layer around them
+    let min_region_row = adw::SpinRow::with_range(0.0, 10000.0, 1.0);
+    min_region_row.set_title("Minimum Region");
+    min_region_row.set_subtitle("Merge smaller islands of a layer into the layer around them");
+    min_region_row.set_value(0.0);
+
+    let region_unit_model = gtk4::StringList::new(&REGION_UNITS);
+    let region_unit_row = adw::ComboRow::builder()
+        .title("Region Unit")
+        .subtitle("Measure the minimum region in pixels or on the model")
+        .model(&region_unit_model)
+        .build();
+
+    let highlight_merged_row = adw::SwitchRow::builder()
+        .title("Highlight Merged Islands")
+        .subtitle("Tint the pixels speckle cleanup moved in the preview")
+        .active(true)
+        .build();
+
+    preferences_group.add(&min_region_row);
+    preferences_group.add(&region_unit_row);
+    preferences_group.add(&highlight_merged_row);
+
+    {
+        let depth_settings = depth_settings.clone();
+        let mesh_options = mesh_options.clone();
+        let img_data = img_data.clone();
+        let region_unit_row = region_unit_row.clone();
+        let preview_area = preview_area.clone();
+        let threshold_area = threshold_area.clone();
+        min_region_row.connect_value_notify(move |row| {
+            depth_settings.borrow_mut().min_region_px = min_region_px(
+                row.value(),
+                region_unit_row.selected(),
+                mesh_options.borrow().dimensions.width_mm,
+                img_data.borrow().as_ref(),
+            );
+            preview_area.queue_draw();
+            threshold_area.queue_draw();
+        });
+    }
+
+    {
+        let depth_settings = depth_settings.clone();
+        let mesh_options = mesh_options.clone();
+        let img_data = img_data.clone();
+        let min_region_row = min_region_row.clone();
+        let preview_area = preview_area.clone();
+        let threshold_area = threshold_area.clone();
+        region_unit_row.connect_selected_notify(move |row| {
+            // Square millimetres are fractional, pixels whole
+            min_region_row.set_digits(if row.selected() == REGION_MM2 { 1 } else { 0 });
+            depth_settings.borrow_mut().min_region_px = min_region_px(
+                min_region_row.value(),
+                row.selected(),
+                mesh_options.borrow().dimensions.width_mm,
+                img_data.borrow().as_ref(),
+            );
+            preview_area.queue_draw();
+            threshold_area.queue_draw();
+        });
+    }
+
+    {
+        let highlight_merged = highlight_merged.clone();
+        let preview_area = preview_area.clone();
+        highlight_merged_row.connect_active_notify(move |row| {
+            *highlight_merged.borrow_mut() = row.is_active();
+            preview_area.queue_draw();
+        });
+    }
+
+    // Levels and a tone curve decide how much depth each range of tones gets
+    let tone_defaults = Tone::default();
+
+    let black_row = adw::SpinRow::with_range(0.0, 1.0, 0.01);
+    black_row.set_title("Black Point");
+    black_row.set_subtitle("Tone that becomes the lowest layer; darker pixels join it");
+    black_row.set_digits(2);
+    black_row.set_value(tone_defaults.black as f64);
+
+    let white_row = adw::SpinRow::with_range(0.0, 1.0, 0.01);
+    white_row.set_title("White Point");
+    white_row.set_subtitle("Tone that becomes the highest layer; brighter pixels join it");
+    white_row.set_digits(2);
+    white_row.set_value(tone_defaults.white as f64);
+
+    let gamma_row = adw::SpinRow::with_range(0.1, 10.0, 0.05);
+    gamma_row.set_title("Gamma");
+    gamma_row.set_subtitle("Above 1 lifts the midtones, below 1 lowers them");
+    gamma_row.set_digits(2);
+    gamma_row.set_value(tone_defaults.gamma as f64);
+
+    // Drag points to shape the curve, click to add one and right-click to remove it
+    let curve_area = DrawingArea::builder().hexpand(true).build();
+    curve_area.set_size_request(-1, 220);
+    let curve_frame = gtk4::Frame::builder()
+        .child(&curve_area)
+        .margin_top(12)
+        .build();
+
+    let reset_tone_button = gtk4::Button::builder()
+        .label("Reset")
+        .valign(gtk4::Align::Center)
+        .build();
+    reset_tone_button.add_css_class("flat");
+
+    let tone_group = adw::PreferencesGroup::builder()
+        .title("Tone")
+        .description(
+            "Spread the layers over the tones that matter; the histogram is behind the curve",
+        )
+        .header_suffix(&reset_tone_button)
+        .build();
+
+    tone_group.add(&black_row);
+    tone_group.add(&white_row);
+    tone_group.add(&gamma_row);
+    tone_group.add(&curve_frame);
+
+    // Histogram of the source after levels, and the settings it was counted with
+    let cached_histogram: Rc<RefCell<Option<(DepthMapBuilder, [u32; 256])>>> =
+        Rc::new(RefCell::new(None));
+
+    {
+        let img_data = img_data.clone();
+        let depth_settings = depth_settings.clone();
+        let cached_histogram = cached_histogram.clone();
+        curve_area.set_draw_func(move |area, cr, width, height| {
+            let settings = depth_settings.borrow().clone();
+
+            let histogram = img_data.borrow().as_ref().map(|img| {
+                // The curve doesn't change what's counted
+                let key = DepthMapBuilder {
+                    tone: Tone {
+                        curve: Vec::new(),
+                        ..settings.tone.clone()
+                    },
+                    ..settings.clone()
+                };
+                let mut cached_histogram = cached_histogram.borrow_mut();
+                match *cached_histogram {
+                    Some((ref cached, histogram)) if *cached == key => histogram,
+                    _ => {
+                        let histogram = key.histogram(img);
+                        *cached_histogram = Some((key, histogram));
+                        histogram
+                    }
+                }
+            });
+
+            draw_curve(area, cr, width, height, &settings.tone, histogram.as_ref());
+        });
+    }
+
+    {
+        // Index of the point being dragged and where it started
+        let dragging: Rc<RefCell<Option<(usize, [f64; 2])>>> = Rc::new(RefCell::new(None));
+        let drag = gtk4::GestureDrag::builder().button(0).build();
+
+        {
+            let depth_settings = depth_settings.clone();
+            let dragging = dragging.clone();
+            let curve_area_for_begin = curve_area.clone();
+            let preview_area = preview_area.clone();
+            let threshold_area = threshold_area.clone();
+            drag.connect_drag_begin(move |gesture, x, y| {
+                let (width, height) = (
+                    curve_area_for_begin.width() as f64,
+                    curve_area_for_begin.height() as f64,
+                );
+                let mut settings = depth_settings.borrow_mut();
+                let curve = &mut settings.tone.curve;
+                let nearest = curve.iter().position(|&point| {
+                    let [px, py] = curve_to_area(point, width, height);
+                    (px - x).hypot(py - y) <= CURVE_POINT_REACH
+                });
+
+                *dragging.borrow_mut() = None;
+                if gesture.current_button() == gtk4::gdk::BUTTON_SECONDARY {
+                    // The two ends always stay
+                    if let Some(i) = nearest.filter(|&i| i > 0 && i + 1 < curve.len()) {
+                        curve.remove(i);
+                    }
+                } else if let Some(i) = nearest {
+                    *dragging.borrow_mut() = Some((i, [x, y]));
+                } else {
+                    let [input, output] = area_to_curve([x, y], width, height);
+                    let i = curve.partition_point(|point| point[0] < input);
+                    // Only between the ends, and not on top of another point
+                    if i > 0 && i < curve.len() && curve[i][0] > input {
+                        curve.insert(i, [input, output]);
+                        *dragging.borrow_mut() = Some((i, [x, y]));
+                    }
+                }
+                drop(settings);
+
+                curve_area_for_begin.queue_draw();
+                preview_area.queue_draw();
+                threshold_area.queue_draw();
+            });
+        }
+
+        {
+            let depth_settings = depth_settings.clone();
+            let dragging = dragging.clone();
+            let curve_area_for_update = curve_area.clone();
+            let preview_area = preview_area.clone();
+            let threshold_area = threshold_area.clone();
+            drag.connect_drag_update(move |_, dx, dy| {
+                let Some((i, [x, y])) = *dragging.borrow() else {
+                    return;
+                };
+                let (width, height) = (
+                    curve_area_for_update.width() as f64,
+                    curve_area_for_update.height() as f64,
+                );
+                let [input, output] = area_to_curve([x + dx, y + dy], width, height);
+
+                let mut settings = depth_settings.borrow_mut();
+                let curve = &mut settings.tone.curve;
+                let last = curve.len() - 1;
+                // Ends slide up and down, the rest stay between their neighbours
+                let input = if i == 0 || i == last {
+                    curve[i][0]
+                } else {
+                    let gap = 1.0 / 255.0;
+                    input.clamp(curve[i - 1][0] + gap, curve[i + 1][0] - gap)
+                };
+                curve[i] = [input, output];
+                drop(settings);
+
+                curve_area_for_update.queue_draw();
+                preview_area.queue_draw();
+                threshold_area.queue_draw();
+            });
+        }
+
+        {
+            let dragging = dragging.clone();
+            drag.connect_drag_end(move |_, _, _| {
+                *dragging.borrow_mut() = None;
+            });
+        }
+
+        curve_area.add_controller(drag);
+    }
+
+    {
+        let threshold_area = threshold_area.clone();
+        let depth_settings = depth_settings.clone();
+        let preview_area = preview_area.clone();
+        let curve_area = curve_area.clone();
+        black_row.connect_value_notify(move |row| {
+            depth_settings.borrow_mut().tone.black = row.value() as f32;
+            curve_area.queue_draw();
+            preview_area.queue_draw();
+            threshold_area.queue_draw();
+        });
+    }
+
+    {
+        let threshold_area = threshold_area.clone();
+        let depth_settings = depth_settings.clone();
+        let preview_area = preview_area.clone();
+        let curve_area = curve_area.clone();
+        white_row.connect_value_notify(move |row| {
+            depth_settings.borrow_mut().tone.white = row.value() as f32;
+            curve_area.queue_draw();
+            preview_area.queue_draw();
+            threshold_area.queue_draw();
+        });
+    }
+
+    {
+        let threshold_area = threshold_area.clone();
+        let depth_settings = depth_settings.clone();
+        let preview_area = preview_area.clone();
+        let curve_area = curve_area.clone();
+        gamma_row.connect_value_notify(move |row| {
+            depth_settings.borrow_mut().tone.gamma = row.value() as f32;
+            curve_area.queue_draw();
+            preview_area.queue_draw();
+            threshold_area.queue_draw();
+        });
+    }
+
+    {
+        let depth_settings = depth_settings.clone();
+        let preview_area = preview_area.clone();
+        let curve_area = curve_area.clone();
+        let black_row = black_row.clone();
+        let white_row = white_row.clone();
+        let gamma_row = gamma_row.clone();
+        let threshold_area = threshold_area.clone();
+        reset_tone_button.connect_clicked(move |_| {
+            // The rows write their defaults back as they change
+            depth_settings.borrow_mut().tone.curve = Tone::default().curve;
+            black_row.set_value(tone_defaults.black as f64);
+            white_row.set_value(tone_defaults.white as f64);
+            gamma_row.set_value(tone_defaults.gamma as f64);
+            curve_area.queue_draw();
+            preview_area.queue_draw();
+            threshold_area.queue_draw();
+        });
+    }
+
+    // The histogram follows the channel and which pixels are cut out
+    {
+        let curve_area = curve_area.clone();
+        channel_row.connect_selected_notify(move |_| curve_area.queue_draw());
+    }
+
+    {
+        let curve_area = curve_area.clone();
+        cut_out_row.connect_active_notify(move |_| curve_area.queue_draw());
+    }
+
+    // Where the layer boundaries fall, and in manual placement where to drag them
+    let quantizer_model = gtk4::StringList::new(&Quantizer::ALL.map(Quantizer::label));
+    let quantizer_row = adw::ComboRow::builder()
+        .title("Layer Placement")
+        .subtitle("How the boundaries between layers are chosen")
+        .model(&quantizer_model)
+        .build();
+
+    threshold_area.set_size_request(-1, 140);
+    let threshold_frame = gtk4::Frame::builder()
+        .child(&threshold_area)
+        .margin_top(12)
+        .build();
+
+    let quantize_group = adw::PreferencesGroup::builder()
+        .title("Layer Thresholds")
+        .description(
+            "Boundaries between layers over the histogram of depths; drag them in manual placement",
+        )
+        .build();
+
+    quantize_group.add(&quantizer_row);
+    quantize_group.add(&threshold_frame);
+
+    // Exact depths of the image and the settings they were built with
+    let cached_depth: Rc<RefCell<Option<(DepthMapBuilder, DepthMap)>>> =
+        Rc::new(RefCell::new(None));
+
+    {
+        let img_data = img_data.clone();
+        let depth_settings = depth_settings.clone();
+        let cached_depth = cached_depth.clone();
+        threshold_area.set_draw_func(move |area, cr, width, height| {
+            let img_data = img_data.borrow();
+            let Some(ref img) = *img_data else {
+                return;
+            };
+            let settings = depth_settings.borrow().clone();
+            let manual = settings.quantizer == Quantizer::Manual;
+            refresh_depth(&cached_depth, img, settings);
+            if let Some((_, ref depth)) = *cached_depth.borrow() {
+                draw_thresholds(area, cr, width, height, depth, manual);
+            }
+        });
+    }
+
+    {
+        // Index of the threshold being dragged and its depth when picked up
+        let dragging: Rc<RefCell<Option<(usize, f32)>>> = Rc::new(RefCell::new(None));
+        let drag = gtk4::GestureDrag::new();
+
+        {
+            let depth_settings = depth_settings.clone();
+            let cached_depth = cached_depth.clone();
+            let dragging = dragging.clone();
+            let threshold_area_for_begin = threshold_area.clone();
+            drag.connect_drag_begin(move |_, x, _| {
+                *dragging.borrow_mut() = None;
+                let mut settings = depth_settings.borrow_mut();
+                if settings.quantizer != Quantizer::Manual {
+                    return;
+                }
+                let cached_depth = cached_depth.borrow();
+                let Some((_, ref depth)) = *cached_depth else {
+                    return;
+                };
+
+                let width = threshold_area_for_begin.width() as f64;
+                let nearest = depth
+                    .thresholds()
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &threshold)| (i, (threshold_to_area(threshold, width) - x).abs()))
+                    .filter(|&(_, distance)| distance <= CURVE_POINT_REACH)
+                    .min_by(|a, b| a.1.total_cmp(&b.1));
+                if let Some((i, _)) = nearest {
+                    // Start from what's shown, which is uniform after the layer count changed
+                    settings.thresholds = depth.thresholds().to_vec();
+                    *dragging.borrow_mut() = Some((i, settings.thresholds[i]));
+                }
+            });
+        }
+
+        {
+            let depth_settings = depth_settings.clone();
+            let dragging = dragging.clone();
+            let threshold_area_for_update = threshold_area.clone();
+            let preview_area = preview_area.clone();
+            drag.connect_drag_update(move |_, dx, _| {
+                let Some((i, start)) = *dragging.borrow() else {
+                    return;
+                };
+                let width = threshold_area_for_update.width() as f64;
+                let x = threshold_to_area(start, width) + dx;
+
+                let mut settings = depth_settings.borrow_mut();
+                let thresholds = &mut settings.thresholds;
+                // Each boundary stays between its neighbours
+                let gap = 1.0 / 255.0;
+                let low = if i > 0 { thresholds[i - 1] + gap } else { gap };
+                let high = thresholds.get(i + 1).map_or(1.0, |next| next - gap);
+                thresholds[i] = area_to_threshold(x, width).clamp(low, high.max(low));
+                drop(settings);
+
+                threshold_area_for_update.queue_draw();
+                preview_area.queue_draw();
+            });
+        }
+
+        {
+            let dragging = dragging.clone();
+            drag.connect_drag_end(move |_, _, _| {
+                *dragging.borrow_mut() = None;
+            });
+        }
+
+        threshold_area.add_controller(drag);
+    }
+
+    {
+        let img_data = img_data.clone();
+        let depth_settings = depth_settings.clone();
+        let preview_area = preview_area.clone();
+        let threshold_area = threshold_area.clone();
+        quantizer_row.connect_selected_notify(move |row| {
+            if let Some(&quantizer) = Quantizer::ALL.get(row.selected() as usize) {
+                let mut settings = depth_settings.borrow_mut();
+                // Manual placement starts from the boundaries shown until now
+                if quantizer == Quantizer::Manual {
+                    if let Some(ref img) = *img_data.borrow() {
+                        settings.thresholds = settings.build(img).thresholds().to_vec();
+                    }
+                }
+                settings.quantizer = quantizer;
+                drop(settings);
+
+                threshold_area.queue_draw();
+                preview_area.queue_draw();
+            }
+        });
+    }
+
+    // Texture toggle for the OBJ and GLB materials
+    let texture_row = adw::SwitchRow::builder()
+        .title("Photo Texture")
+        .subtitle("Map the source image onto OBJ and GLB meshes")
+        .active(true)
+        .build();
+
+    preferences_group.add(&texture_row);
+
+    {
+        let export_texture = export_texture.clone();
+        texture_row.connect_active_notify(move |row| {
+            *export_texture.borrow_mut() = row.is_active();
+        });
+    }
+
+    // Solid mode closes the back and sides for printing
+    let solid_row = adw::SwitchRow::builder()
+        .title("Solid")
+        .subtitle("Add a base plate and side walls so the mesh is printable")
+        .build();
+
+    preferences_group.add(&solid_row);
+
+    // One body per layer, so multi-material slicers can colour each
+    let split_row = adw::SwitchRow::builder()
+        .title("Split Layers")
+        .subtitle("Export a separate solid per layer, one object each in 3MF files")
+        .build();
+
+    preferences_group.add(&split_row);
+
+    // Smooth slopes or crisp terraces between layers
+    let style_model = gtk4::StringList::new(&MeshStyle::ALL.map(MeshStyle::label));
+    let style_row = adw::ComboRow::builder()
+        .title("Mesh Style")
+        .subtitle("Terraced keeps vertical steps between layers")
+        .model(&style_model)
+        .build();
+
+    preferences_group.add(&style_row);
+
+    {
+        let mesh_options = mesh_options.clone();
+        let preview_area = preview_area.clone();
+        style_row.connect_selected_notify(move |row| {
+            if let Some(&style) = MeshStyle::ALL.get(row.selected() as usize) {
+                mesh_options.borrow_mut().style = style;
+                preview_area.queue_draw();
+            }
+        });
+    }
+
+    // Wrapping bends the relief around a curved surface
+    let projection_model = gtk4::StringList::new(&Projection::ALL.map(Projection::label));
+    let projection_row = adw::ComboRow::builder()
+        .title("Projection")
+        .subtitle("Wrap the relief around a curved surface; the width becomes its circumference")
+        .model(&projection_model)
+        .build();
+
+    preferences_group.add(&projection_row);
+
+    {
+        let mesh_options = mesh_options.clone();
+        let style_row = style_row.clone();
+        let preview_area = preview_area.clone();
+        projection_row.connect_selected_notify(move |row| {
+            if let Some(&projection) = Projection::ALL.get(row.selected() as usize) {
+                mesh_options.borrow_mut().projection = projection;
+                preview_area.queue_draw();
+                // Wrapped surfaces are always smooth
+                style_row.set_sensitive(projection == Projection::Flat);
+            }
+        });
+    }
+
+    // Adaptive simplification merges flat regions into larger faces
+    let simplify_row = adw::SwitchRow::builder()
+        .title("Simplify")
+        .subtitle("Merge flat regions into larger faces")
+        .active(true)
+        .build();
+
+    let tolerance_row = adw::SpinRow::with_range(0.0, 5.0, 0.01);
+    tolerance_row.set_title("Tolerance (mm)");
+    tolerance_row.set_subtitle("Height error allowed when merging smooth faces");
+    tolerance_row.set_digits(2);
+    tolerance_row.set_value(MeshOptions::default().tolerance_mm as f64);
+
+    simplify_row
+        .bind_property("active", &tolerance_row, "sensitive")
+        .sync_create()
+        .build();
+
+    preferences_group.add(&simplify_row);
+    preferences_group.add(&tolerance_row);
+
+    {
+        let mesh_options = mesh_options.clone();
+        let preview_area = preview_area.clone();
+        simplify_row.connect_active_notify(move |row| {
+            mesh_options.borrow_mut().simplify = row.is_active();
+            preview_area.queue_draw();
+        });
+    }
+
+    {
+        let mesh_options = mesh_options.clone();
+        let preview_area = preview_area.clone();
+        tolerance_row.connect_value_notify(move |row| {
+            mesh_options.borrow_mut().tolerance_mm = row.value() as f32;
+            preview_area.queue_draw();
+        });
+    }
+
+    // Physical size of the exported model; width and height keep the image's aspect ratio
+    let defaults = MeshOptions::default().dimensions;
+
+    let width_row = adw::SpinRow::with_range(1.0, 2000.0, 1.0);
+    width_row.set_title("Width (mm)");
+    width_row.set_subtitle("Size of the model along the image's width");
+    width_row.set_digits(1);
+    width_row.set_value(defaults.width_mm as f64);
+
+    let height_row = adw::SpinRow::with_range(1.0, 2000.0, 1.0);
+    height_row.set_title("Height (mm)");
+    height_row.set_subtitle("Follows the width to keep the aspect ratio");
+    height_row.set_digits(1);
+    height_row.set_value(defaults.width_mm as f64);
+
+    let relief_row = adw::SpinRow::with_range(0.1, 100.0, 0.1);
+    relief_row.set_title("Relief Depth (mm)");
+    relief_row.set_subtitle("Height difference between the lowest and highest layer");
+    relief_row.set_digits(1);
+    relief_row.set_value(defaults.relief_mm as f64);
+
+    let thickness_row = adw::SpinRow::with_range(0.0, 50.0, 0.1);
+    thickness_row.set_title("Minimum Thickness (mm)");
+    thickness_row.set_subtitle("Material below the lowest layer");
+    thickness_row.set_digits(1);
+    thickness_row.set_value(defaults.min_thickness_mm as f64);
+
+    preferences_group.add(&width_row);
+    preferences_group.add(&height_row);
+    preferences_group.add(&relief_row);
+    preferences_group.add(&thickness_row);
+
+    {
+        let mesh_options = mesh_options.clone();
+        let preview_area = preview_area.clone();
+        solid_row.connect_active_notify(move |row| {
+            mesh_options.borrow_mut().solid = row.is_active();
+            preview_area.queue_draw();
+        });
+    }
+
+    {
+        let mesh_options = mesh_options.clone();
+        let preview_area = preview_area.clone();
+        split_row.connect_active_notify(move |row| {
+            mesh_options.borrow_mut().split_layers = row.is_active();
+            preview_area.queue_draw();
+        });
+    }
+
+    {
+        let mesh_options = mesh_options.clone();
+        let img_data = img_data.clone();
+        let syncing_size = syncing_size.clone();
+        let height_row = height_row.clone();
+        let preview_area = preview_area.clone();
+        let depth_settings = depth_settings.clone();
+        let min_region_row = min_region_row.clone();
+        let region_unit_row = region_unit_row.clone();
+        let threshold_area = threshold_area.clone();
+        width_row.connect_value_notify(move |row| {
+            if *syncing_size.borrow() {
+                return;
+            }
+            mesh_options.borrow_mut().dimensions.width_mm = row.value() as f32;
+            preview_area.queue_draw();
+            if let Some(ref img) = *img_data.borrow() {
+                let height_mm = mesh_options
+                    .borrow()
+                    .dimensions
+                    .height_mm(img.width(), img.height());
+                *syncing_size.borrow_mut() = true;
+                height_row.set_value(height_mm as f64);
+                *syncing_size.borrow_mut() = false;
+
+                // Square millimetres cover more or fewer pixels at the new size
+                depth_settings.borrow_mut().min_region_px = min_region_px(
+                    min_region_row.value(),
+                    region_unit_row.selected(),
+                    row.value() as f32,
+                    Some(img),
+                );
+                threshold_area.queue_draw();
+            }
+        });
+    }
+
+    {
+        let mesh_options = mesh_options.clone();
+        let img_data = img_data.clone();
+        let syncing_size = syncing_size.clone();
+        let width_row = width_row.clone();
+        let preview_area = preview_area.clone();
+        let depth_settings = depth_settings.clone();
+        let min_region_row = min_region_row.clone();
+        let region_unit_row = region_unit_row.clone();
+        let threshold_area = threshold_area.clone();
+        height_row.connect_value_notify(move |row| {
+            if *syncing_size.borrow() {
+                return;
+            }
+            if let Some(ref img) = *img_data.borrow() {
+                let width_mm = row.value() as f32 * img.width() as f32 / img.height().max(1) as f32;
+                mesh_options.borrow_mut().dimensions.width_mm = width_mm;
+                preview_area.queue_draw();
+                *syncing_size.borrow_mut() = true;
+                width_row.set_value(width_mm as f64);
+                *syncing_size.borrow_mut() = false;
+
+                depth_settings.borrow_mut().min_region_px = min_region_px(
+                    min_region_row.value(),
+                    region_unit_row.selected(),
+                    width_mm,
+                    Some(img),
+                );
+                threshold_area.queue_draw();
+            }
+        });
+    }
+
+    {
+        let mesh_options = mesh_options.clone();
+        let preview_area = preview_area.clone();
+        relief_row.connect_value_notify(move |row| {
+            mesh_options.borrow_mut().dimensions.relief_mm = row.value() as f32;
+            // Layer thickness changes the predicted filament colours
+            preview_area.queue_draw();
+        });
+    }
+
+    {
+        let mesh_options = mesh_options.clone();
+        let preview_area = preview_area.clone();
+        thickness_row.connect_value_notify(move |row| {
+            mesh_options.borrow_mut().dimensions.min_thickness_mm = row.value() as f32;
+            preview_area.queue_draw();
+        });
+    }
+
+    // Lithophane mode swaps the relief for a backlit panel of varying thickness
+    let lithophane_defaults = Lithophane::default();
+
+    let lithophane_row = adw::SwitchRow::builder()
+        .title("Lithophane")
+        .subtitle("Thicker where the image is darker, for backlighting")
+        .build();
+
+    let min_wall_row = adw::SpinRow::with_range(0.2, 20.0, 0.1);
+    min_wall_row.set_title("Minimum Wall (mm)");
+    min_wall_row.set_subtitle("Thickness behind the brightest areas");
+    min_wall_row.set_digits(1);
+    min_wall_row.set_value(lithophane_defaults.min_thickness_mm as f64);
+
+    let max_wall_row = adw::SpinRow::with_range(0.2, 20.0, 0.1);
+    max_wall_row.set_title("Maximum Wall (mm)");
+    max_wall_row.set_subtitle("Thickness behind the darkest areas and of the frame");
+    max_wall_row.set_digits(1);
+    max_wall_row.set_value(lithophane_defaults.max_thickness_mm as f64);
+
+    let frame_row = adw::SpinRow::with_range(0.0, 50.0, 0.5);
+    frame_row.set_title("Frame (mm)");
+    frame_row.set_subtitle("Border around the image at full thickness");
+    frame_row.set_digits(1);
+    frame_row.set_value(lithophane_defaults.frame_mm as f64);
+
+    let curve_model = gtk4::StringList::new(&Curve::ALL.map(Curve::label));
+    let curve_row = adw::ComboRow::builder()
+        .title("Panel Shape")
+        .subtitle("Curved panels stand upright with the image facing out")
+        .model(&curve_model)
+        .build();
+
+    let arc_row = adw::SpinRow::with_range(10.0, 350.0, 5.0);
+    arc_row.set_title("Arc Angle (°)");
+    arc_row.set_subtitle("How far an arc-curved panel wraps around");
+    arc_row.set_digits(0);
+    arc_row.set_value(lithophane_defaults.arc_degrees as f64);
+    arc_row.set_sensitive(false);
+
+    let lithophane_group = adw::PreferencesGroup::builder().title("Lithophane").build();
+
+    lithophane_group.add(&lithophane_row);
+    for row in [&min_wall_row, &max_wall_row, &frame_row] {
+        lithophane_row
+            .bind_property("active", row, "sensitive")
+            .sync_create()
+            .build();
+        lithophane_group.add(row);
+    }
+    lithophane_row
+        .bind_property("active", &curve_row, "sensitive")
+        .sync_create()
+        .build();
+    lithophane_group.add(&curve_row);
+    lithophane_group.add(&arc_row);
+
+    // The lithophane sets its own shape and thickness and is always solid
+    for row in [
+        solid_row.upcast_ref::<gtk4::Widget>(),
+        split_row.upcast_ref(),
+        projection_row.upcast_ref(),
+        relief_row.upcast_ref(),
+        thickness_row.upcast_ref(),
+    ] {
+        lithophane_row
+            .bind_property("active", row, "sensitive")
+            .invert_boolean()
+            .sync_create()
+            .build();
+    }
+
+    {
+        let lithophane_mode = lithophane_mode.clone();
+        let arc_row = arc_row.clone();
+        let curve_row = curve_row.clone();
+        let preview_area = preview_area.clone();
+        lithophane_row.connect_active_notify(move |row| {
+            *lithophane_mode.borrow_mut() = row.is_active();
+            preview_area.queue_draw();
+            let arc = Curve::ALL.get(curve_row.selected() as usize) == Some(&Curve::Arc);
+            arc_row.set_sensitive(row.is_active() && arc);
+        });
+    }
+
+    {
+        let lithophane = lithophane.clone();
+        let preview_area = preview_area.clone();
+        min_wall_row.connect_value_notify(move |row| {
+            lithophane.borrow_mut().min_thickness_mm = row.value() as f32;
+            preview_area.queue_draw();
+        });
+    }
+
+    {
+        let lithophane = lithophane.clone();
+        let preview_area = preview_area.clone();
+        max_wall_row.connect_value_notify(move |row| {
+            lithophane.borrow_mut().max_thickness_mm = row.value() as f32;
+            preview_area.queue_draw();
+        });
+    }
+
+    {
+        let lithophane = lithophane.clone();
+        let preview_area = preview_area.clone();
+        frame_row.connect_value_notify(move |row| {
+            lithophane.borrow_mut().frame_mm = row.value() as f32;
+            preview_area.queue_draw();
+        });
+    }
+
+    {
+        let lithophane = lithophane.clone();
+        let arc_row = arc_row.clone();
+        let lithophane_row = lithophane_row.clone();
+        let preview_area = preview_area.clone();
+        curve_row.connect_selected_notify(move |row| {
+            if let Some(&curve) = Curve::ALL.get(row.selected() as usize) {
+                lithophane.borrow_mut().curve = curve;
+                preview_area.queue_draw();
+                arc_row.set_sensitive(lithophane_row.is_active() && curve == Curve::Arc);
+            }
+        });
+    }
+
+    {
+        let lithophane = lithophane.clone();
+        let preview_area = preview_area.clone();
+        arc_row.connect_value_notify(move |row| {
+            lithophane.borrow_mut().arc_degrees = row.value() as f32;
+            preview_area.queue_draw();
+        });
+    }
+
+    // Shadow box stencils are cut from paper, one sheet per layer
+    let stencil_defaults = StencilOptions::default();
+
+    let sheet_frame_row = adw::SpinRow::with_range(0.0, 50.0, 0.5);
+    sheet_frame_row.set_title("Sheet Frame (mm)");
+    sheet_frame_row.set_subtitle("Border of paper around every sheet");
+    sheet_frame_row.set_digits(1);
+    sheet_frame_row.set_value(stencil_defaults.frame_mm as f64);
+
+    let bridges_row = adw::SwitchRow::builder()
+        .title("Bridges")
+        .subtitle("Hold islands in place so each sheet cuts as one piece")
+        .active(stencil_defaults.connect)
+        .build();
+
+    let bridge_row = adw::SpinRow::with_range(0.1, 10.0, 0.1);
+    bridge_row.set_title("Bridge Width (mm)");
+    bridge_row.set_digits(1);
+    bridge_row.set_value(stencil_defaults.bridge_mm as f64);
+
+    bridges_row
+        .bind_property("active", &bridge_row, "sensitive")
+        .sync_create()
+        .build();
+
+    let separate_row = adw::SwitchRow::builder()
+        .title("Separate Sheets")
+        .subtitle("Save one SVG per layer instead of one layered file")
+        .build();
+
+    let stencil_group = adw::PreferencesGroup::builder()
+        .title("Shadow Box")
+        .description("Used when exporting SVG stencils")
+        .build();
+
+    stencil_group.add(&sheet_frame_row);
+    stencil_group.add(&bridges_row);
+    stencil_group.add(&bridge_row);
+    stencil_group.add(&separate_row);
+
+    {
+        let stencil_options = stencil_options.clone();
+        sheet_frame_row.connect_value_notify(move |row| {
+            stencil_options.borrow_mut().frame_mm = row.value() as f32;
+        });
+    }
+
+    {
+        let stencil_options = stencil_options.clone();
+        bridges_row.connect_active_notify(move |row| {
+            stencil_options.borrow_mut().connect = row.is_active();
+        });
+    }
+
+    {
+        let stencil_options = stencil_options.clone();
+        bridge_row.connect_value_notify(move |row| {
+            stencil_options.borrow_mut().bridge_mm = row.value() as f32;
+        });
+    }
+
+    {
+        let separate_sheets = separate_sheets.clone();
+        separate_row.connect_active_notify(move |row| {
+            *separate_sheets.borrow_mut() = row.is_active();
+        });
+    }
+
+    // PLY extras for point cloud tools such as MeshLab
+    let points_row = adw::SwitchRow::builder()
+        .title("Points Only")
+        .subtitle("Write the full-resolution height field as coloured points, without faces")
+        .build();
+
+    let layer_property_row = adw::SwitchRow::builder()
+        .title("Layer Property")
+        .subtitle("Store each vertex's depth layer alongside its colour")
+        .build();
+
+    let ply_group = adw::PreferencesGroup::builder()
+        .title("Point Cloud")
+        .description("Used when exporting PLY files")
+        .build();
+
+    ply_group.add(&points_row);
+    ply_group.add(&layer_property_row);
+
+    {
+        let ply_points = ply_points.clone();
+        points_row.connect_active_notify(move |row| {
+            *ply_points.borrow_mut() = row.is_active();
+        });
+    }
+
+    {
+        let ply_layers = ply_layers.clone();
+        layer_property_row.connect_active_notify(move |row| {
+            *ply_layers.borrow_mut() = row.is_active();
+        });
+    }
+
+    // Bit depth of the depth map saved as an image
+    let sixteen_bit_row = adw::SwitchRow::builder()
+        .title("16-bit")
+        .subtitle("Save 65536 gray levels instead of 256")
+        .active(true)
+        .build();
+
+    let heightmap_group = adw::PreferencesGroup::builder()
+        .title("Heightmap")
+        .description("Used when exporting PNG or TIFF heightmaps")
+        .build();
+
+    heightmap_group.add(&sixteen_bit_row);
+
+    {
+        let heightmap_16bit = heightmap_16bit.clone();
+        sixteen_bit_row.connect_active_notify(move |row| {
+            *heightmap_16bit.borrow_mut() = row.is_active();
+        });
+    }
+
+    // Filament colours per layer, for prints that swap filament at set heights
+    let filament_row = adw::SwitchRow::builder()
+        .title("Filament Colours")
+        .subtitle("Preview the printed colours and save a swap schedule with the mesh")
+        .build();
+
+    let layer_height_row = adw::SpinRow::with_range(0.04, 0.4, 0.01);
+    layer_height_row.set_title("Print Layer Height (mm)");
+    layer_height_row.set_subtitle("Numbers the layers in the swap schedule");
+    layer_height_row.set_digits(2);
+    layer_height_row.set_value(filament_plan.borrow().layer_height_mm as f64);
+
+    let filaments_row = adw::ExpanderRow::builder()
+        .title("Layer Filaments")
+        .subtitle("Colour and transmission distance, from the bottom layer up")
+        .build();
+    let filament_rows: Rc<RefCell<Vec<adw::ActionRow>>> = Rc::new(RefCell::new(Vec::new()));
+    fill_filament_rows(
+        &filaments_row,
+        &filament_rows,
+        &filament_plan,
+        &preview_area,
+    );
+
+    for row in [
+        layer_height_row.upcast_ref::<gtk4::Widget>(),
+        filaments_row.upcast_ref(),
+    ] {
+        filament_row
+            .bind_property("active", row, "sensitive")
+            .sync_create()
+            .build();
+    }
+
+    let filament_group = adw::PreferencesGroup::builder().title("Filaments").build();
+
+    filament_group.add(&filament_row);
+    filament_group.add(&layer_height_row);
+    filament_group.add(&filaments_row);
+
+    {
+        let filament_mode = filament_mode.clone();
+        let preview_area = preview_area.clone();
+        filament_row.connect_active_notify(move |row| {
+            *filament_mode.borrow_mut() = row.is_active();
+            preview_area.queue_draw();
+        });
+    }
+
+    {
+        let filament_plan = filament_plan.clone();
+        layer_height_row.connect_value_notify(move |row| {
+            filament_plan.borrow_mut().layer_height_mm = row.value() as f32;
+        });
+    }
+
+    // One filament row per depth layer
+    {
+        let filament_plan = filament_plan.clone();
+        let filaments_row = filaments_row.clone();
+        let filament_rows = filament_rows.clone();
+        let preview_area = preview_area.clone();
+        spin_button.connect_value_changed(move |s| {
+            filament_plan.borrow_mut().resize(s.value() as u8);
+            fill_filament_rows(
+                &filaments_row,
+                &filament_rows,
+                &filament_plan,
+                &preview_area,
+            );
         });
     }
 
@@ -268,7 +1713,7 @@ fn build_ui(app: &adw::Application) {
     let preview_clamp = adw::Clamp::builder()
         .maximum_size(800)
         .tightening_threshold(600)
-        .child(&preview_frame)
+        .child(&preview_box)
         .build();
 
     // Content box with proper spacing
@@ -283,6 +1728,13 @@ fn build_ui(app: &adw::Application) {
 
     content.append(&preview_clamp);
     content.append(&preferences_group);
+    content.append(&tone_group);
+    content.append(&quantize_group);
+    content.append(&lithophane_group);
+    content.append(&filament_group);
+    content.append(&stencil_group);
+    content.append(&ply_group);
+    content.append(&heightmap_group);
 
     // Add scrolled window for better handling of smaller screens
     let scrolled_window = gtk4::ScrolledWindow::builder()
@@ -315,11 +1767,23 @@ fn build_ui(app: &adw::Application) {
     {
         let app = app.clone();
         let img_data = img_data.clone();
+        let image_path = image_path.clone();
         let preview_area = preview_area.clone();
         let file_chooser_ref = file_chooser_ref.clone();
         let window_title = window_title.clone();
         let toast_overlay = toast_overlay.clone();
         let cached_surface = cached_surface.clone();
+        let cached_model = cached_model.clone();
+        let cached_histogram = cached_histogram.clone();
+        let curve_area = curve_area.clone();
+        let cached_depth = cached_depth.clone();
+        let threshold_area = threshold_area.clone();
+        let depth_settings = depth_settings.clone();
+        let min_region_row = min_region_row.clone();
+        let region_unit_row = region_unit_row.clone();
+        let mesh_options = mesh_options.clone();
+        let syncing_size = syncing_size.clone();
+        let height_row = height_row.clone();
 
         open_button.connect_clicked(move |_| {
             let file_chooser = gtk4::FileChooserNative::builder()
@@ -351,11 +1815,23 @@ fn build_ui(app: &adw::Application) {
 
             file_chooser.connect_response({
                 let img_data = img_data.clone();
+                let image_path = image_path.clone();
                 let preview_area = preview_area.clone();
                 let file_chooser_ref = file_chooser_ref.clone();
                 let window_title = window_title.clone();
                 let toast_overlay = toast_overlay.clone();
                 let cached_surface = cached_surface.clone();
+                let cached_model = cached_model.clone();
+                let cached_histogram = cached_histogram.clone();
+                let curve_area = curve_area.clone();
+                let cached_depth = cached_depth.clone();
+                let threshold_area = threshold_area.clone();
+                let depth_settings = depth_settings.clone();
+                let min_region_row = min_region_row.clone();
+                let region_unit_row = region_unit_row.clone();
+                let mesh_options = mesh_options.clone();
+                let syncing_size = syncing_size.clone();
+                let height_row = height_row.clone();
 
                 move |dialog, response| {
                     if response == gtk4::ResponseType::Accept {
@@ -370,10 +1846,32 @@ fn build_ui(app: &adw::Application) {
 
                                 match image::open(&path) {
                                     Ok(img) => {
-                                        *img_data.borrow_mut() = Some(img.to_rgb8());
+                                        // Keep the width and let the height follow the new aspect ratio
+                                        let height_mm = mesh_options
+                                            .borrow()
+                                            .dimensions
+                                            .height_mm(img.width(), img.height());
+                                        *syncing_size.borrow_mut() = true;
+                                        height_row.set_value(height_mm as f64);
+                                        *syncing_size.borrow_mut() = false;
+
+                                        depth_settings.borrow_mut().min_region_px = min_region_px(
+                                            min_region_row.value(),
+                                            region_unit_row.selected(),
+                                            mesh_options.borrow().dimensions.width_mm,
+                                            Some(&img),
+                                        );
+
+                                        *img_data.borrow_mut() = Some(img);
+                                        *image_path.borrow_mut() = Some(path.clone());
                                         // Clear the cache when loading a new image
                                         *cached_surface.borrow_mut() = None;
+                                        *cached_model.borrow_mut() = None;
+                                        *cached_histogram.borrow_mut() = None;
+                                        *cached_depth.borrow_mut() = None;
                                         preview_area.queue_draw();
+                                        curve_area.queue_draw();
+                                        threshold_area.queue_draw();
 
                                         let toast = adw::Toast::new("Image loaded successfully");
                                         toast_overlay.add_toast(toast);
@@ -402,23 +1900,52 @@ fn build_ui(app: &adw::Application) {
     // Save button handler
     {
         let img_data = img_data.clone();
-        let num_layers = num_layers.clone();
+        let image_path = image_path.clone();
+        let depth_settings = depth_settings.clone();
+        let export_texture = export_texture.clone();
+        let mesh_options = mesh_options.clone();
+        let lithophane = lithophane.clone();
+        let lithophane_mode = lithophane_mode.clone();
+        let stencil_options = stencil_options.clone();
+        let separate_sheets = separate_sheets.clone();
+        let ply_points = ply_points.clone();
+        let ply_layers = ply_layers.clone();
+        let heightmap_16bit = heightmap_16bit.clone();
+        let filament_plan = filament_plan.clone();
+        let filament_mode = filament_mode.clone();
         let toast_overlay = toast_overlay.clone();
         let window = window.clone();
 
         save_button.connect_clicked(move |_| {
             if let Some(ref img) = *img_data.borrow() {
                 let file_chooser = gtk4::FileChooserNative::builder()
-                    .title("Export as OBJ")
+                    .title("Export Mesh")
                     .action(FileChooserAction::Save)
                     .accept_label("Export")
                     .build();
 
-                // Add OBJ file filter
-                let filter = gtk4::FileFilter::new();
-                filter.set_name(Some("OBJ files"));
-                filter.add_pattern("*.obj");
-                file_chooser.add_filter(&filter);
+                // One filter per export format; the selected filter picks the writer
+                for format in ExportFormat::ALL {
+                    let filter = gtk4::FileFilter::new();
+                    filter.set_name(Some(format.label()));
+                    filter.add_pattern(&format!("*.{}", format.extension()));
+                    file_chooser.add_filter(&filter);
+                }
+
+                let filter_svg = gtk4::FileFilter::new();
+                filter_svg.set_name(Some(STENCIL_FILTER));
+                filter_svg.add_pattern("*.svg");
+                file_chooser.add_filter(&filter_svg);
+
+                for (name, extension) in HEIGHTMAP_FILTERS {
+                    let filter = gtk4::FileFilter::new();
+                    filter.set_name(Some(name));
+                    filter.add_pattern(&format!("*.{}", extension));
+                    if extension == "tiff" {
+                        filter.add_pattern("*.tif");
+                    }
+                    file_chooser.add_filter(&filter);
+                }
 
                 let filter_all = gtk4::FileFilter::new();
                 filter_all.set_name(Some("All files"));
@@ -428,18 +1955,184 @@ fn build_ui(app: &adw::Application) {
                 file_chooser.set_current_name("output.obj");
                 file_chooser.set_transient_for(Some(&window));
 
+                // Keep the file name's extension in step with the chosen format
+                file_chooser.connect_filter_notify(|dialog| {
+                    let extension =
+                        dialog
+                            .filter()
+                            .and_then(|filter| filter.name())
+                            .and_then(|name| match ExportFormat::from_label(&name) {
+                                Some(format) => Some(format.extension()),
+                                None if name == STENCIL_FILTER => Some("svg"),
+                                None => HEIGHTMAP_FILTERS
+                                    .iter()
+                                    .find(|(filter, _)| *filter == name)
+                                    .map(|&(_, extension)| extension),
+                            });
+                    if let (Some(extension), Some(name)) = (extension, dialog.current_name()) {
+                        let name = Path::new(name.as_str()).with_extension(extension);
+                        dialog.set_current_name(&name.to_string_lossy());
+                    }
+                });
+
                 let img_clone = img.clone();
-                let layers = *num_layers.borrow();
+                let source = image_path.borrow().clone();
+                let settings = depth_settings.borrow().clone();
+                let texture = *export_texture.borrow();
+                let mut options = *mesh_options.borrow();
+                options.lithophane = lithophane_mode.borrow().then(|| *lithophane.borrow());
+                let stencil = StencilOptions {
+                    width_mm: options.dimensions.width_mm,
+                    ..*stencil_options.borrow()
+                };
+                let layered = !*separate_sheets.borrow();
+                let points = *ply_points.borrow();
+                let layer_property = *ply_layers.borrow();
+                let sixteen_bit = *heightmap_16bit.borrow();
+                let plan = filament_mode
+                    .borrow()
+                    .then(|| filament_plan.borrow().clone());
                 let toast_overlay = toast_overlay.clone();
 
                 file_chooser.connect_response(move |dialog, response| {
                     if response == gtk4::ResponseType::Accept {
                         if let Some(file) = dialog.file() {
                             if let Some(path) = file.path() {
-                                match save_as_obj(&img_clone, layers, path.to_str().unwrap()) {
+                                // Refuse to write over the image being converted
+                                let replaces_source = |output: &Path| {
+                                    source
+                                        .as_deref()
+                                        .is_some_and(|source| is_same_file(output, source))
+                                };
+                                if replaces_source(&path) {
+                                    let toast = adw::Toast::new(
+                                        "Choose another name; this would replace the image",
+                                    );
+                                    toast.set_timeout(5);
+                                    toast_overlay.add_toast(toast);
+                                    dialog.destroy();
+                                    return;
+                                }
+
+                                // The stencil filter, or an .svg name under "All files", cuts paper
+                                let filter_name = dialog.filter().and_then(|filter| filter.name());
+                                let filter_name = filter_name.as_deref();
+                                let stencils = filter_name == Some(STENCIL_FILTER)
+                                    || (filter_name.and_then(ExportFormat::from_label).is_none()
+                                        && path.extension().is_some_and(|ext| ext == "svg"));
+                                if stencils {
+                                    let depth = settings.build(&img_clone);
+                                    let sheets = Stencils::build(&depth, &stencil);
+                                    let exporter = SvgExporter { layered };
+                                    let message = match exporter.export(&sheets, &path) {
+                                        Ok(_) => {
+                                            format!("{} exported successfully", STENCIL_FILTER)
+                                        }
+                                        Err(e) => format!("Failed to export: {}", e),
+                                    };
+                                    toast_overlay.add_toast(adw::Toast::new(&message));
+                                    dialog.destroy();
+                                    return;
+                                }
+
+                                // Likewise a heightmap filter, or a .png or .tiff name
+                                let heightmap = match filter_name {
+                                    Some(name) if ExportFormat::from_label(name).is_some() => false,
+                                    Some(STENCIL_FILTER) => false,
+                                    Some(name)
+                                        if HEIGHTMAP_FILTERS.iter().any(|(f, _)| *f == name) =>
+                                    {
+                                        true
+                                    }
+                                    _ => path.extension().is_some_and(|ext| {
+                                        ext == "png" || ext == "tif" || ext == "tiff"
+                                    }),
+                                };
+                                if heightmap {
+                                    let depth = settings.build(&img_clone);
+                                    let exporter = HeightmapExporter { sixteen_bit };
+                                    let message = match exporter.export(&depth, &path) {
+                                        Ok(_) => "Heightmap exported successfully".to_string(),
+                                        Err(e) => format!("Failed to export: {}", e),
+                                    };
+                                    toast_overlay.add_toast(adw::Toast::new(&message));
+                                    dialog.destroy();
+                                    return;
+                                }
+
+                                // "All files" falls back to the extension, then to OBJ
+                                let format = dialog
+                                    .filter()
+                                    .and_then(|filter| filter.name())
+                                    .and_then(|name| ExportFormat::from_label(&name))
+                                    .or_else(|| ExportFormat::from_path(&path))
+                                    .unwrap_or(ExportFormat::Obj);
+
+                                // OBJ files bring their texture along
+                                if format == ExportFormat::Obj
+                                    && texture
+                                    && replaces_source(&ObjExporter::texture_path(&path))
+                                {
+                                    let toast = adw::Toast::new(
+                                        "Choose another name; its texture would replace the image",
+                                    );
+                                    toast.set_timeout(5);
+                                    toast_overlay.add_toast(toast);
+                                    dialog.destroy();
+                                    return;
+                                }
+
+                                let ply =
+                                    matches!(format, ExportFormat::Ply | ExportFormat::PlyAscii);
+                                let mut options = options;
+                                if ply && points {
+                                    // One point per pixel, without the base and walls
+                                    options.simplify = false;
+                                    options.solid = false;
+                                    options.split_layers = false;
+                                    options.style = MeshStyle::Smooth;
+                                    options.lithophane = None;
+                                }
+
+                                let depth = settings.build(&img_clone);
+                                let mesh = Mesh::build(&depth, &options);
+                                let texture = texture.then(|| img_clone.to_rgba8());
+                                let exporter: Box<dyn Exporter> = match (format, &plan) {
+                                    // 3MF layers take their colours from the filaments
+                                    (ExportFormat::ThreeMf, Some(plan)) => {
+                                        Box::new(ThreeMfExporter {
+                                            colors: plan
+                                                .filaments
+                                                .iter()
+                                                .map(|filament| filament.color)
+                                                .collect(),
+                                        })
+                                    }
+                                    (ExportFormat::Ply | ExportFormat::PlyAscii, _) => {
+                                        Box::new(PlyExporter {
+                                            ascii: format == ExportFormat::PlyAscii,
+                                            texture: texture.as_ref(),
+                                            layers: layer_property.then_some(&depth),
+                                            points,
+                                        })
+                                    }
+                                    _ => format.exporter(texture.as_ref()),
+                                };
+                                // The swap schedule goes next to the mesh
+                                let result =
+                                    exporter.export(&mesh, &path).and_then(|()| match &plan {
+                                        Some(plan) => plan.write_schedule(
+                                            &options.dimensions,
+                                            &FilamentPlan::schedule_path(&path),
+                                        ),
+                                        None => Ok(()),
+                                    });
+                                match result {
                                     Ok(_) => {
-                                        let toast =
-                                            adw::Toast::new("OBJ file exported successfully");
+                                        let toast = adw::Toast::new(&format!(
+                                            "{} file exported successfully",
+                                            format.label()
+                                        ));
                                         toast_overlay.add_toast(toast);
                                     }
                                     Err(e) => {
@@ -501,40 +2194,307 @@ fn build_ui(app: &adw::Application) {
     window.present();
 }
 
-// Save grayscale .obj with error handling
-fn save_as_obj(img: &RgbImage, layers: u8, path: &str) -> std::io::Result<()> {
-    let width = img.width() as usize;
-    let height = img.height() as usize;
-    let scale = 0.1;
-    let base_height = 0.0;
-    let mut file = File::create(path)?;
-
-    let layer_scale = 1.0 / (layers as f32 - 1.0);
-
-    writeln!(file, "mtllib material.mtl\nusemtl plane_material\nnewmtl plane_material\nKd 1.0 1.0 1.0\nKa 0.0 0.0 0.0")?;
-
-    for y in 0..height {
-        for x in 0..width {
-            let pixel = img.get_pixel(x as u32, y as u32);
-            let gray = 0.299 * pixel[0] as f32 + 0.587 * pixel[1] as f32 + 0.114 * pixel[2] as f32;
-            let quantized = (gray / 255.0 * (layers as f32 - 1.0)).round() * layer_scale;
-            let z = quantized * scale + base_height;
-            // Negate the Y coordinate to rotate 180 degrees around X axis
-            writeln!(file, "v {} {} {}", x as f32 * scale, -(y as f32 * scale), z)?;
+// Position in the tone curve editor of a curve point in 0.0..=1.0
+fn curve_to_area([input, output]: [f32; 2], width: f64, height: f64) -> [f64; 2] {
+    [
+        CURVE_MARGIN + input as f64 * (width - 2.0 * CURVE_MARGIN),
+        height - CURVE_MARGIN - output as f64 * (height - 2.0 * CURVE_MARGIN),
+    ]
+}
+
+// Curve point under a position in the tone curve editor
+fn area_to_curve([x, y]: [f64; 2], width: f64, height: f64) -> [f32; 2] {
+    let input = (x - CURVE_MARGIN) / (width - 2.0 * CURVE_MARGIN).max(1.0);
+    let output = (height - CURVE_MARGIN - y) / (height - 2.0 * CURVE_MARGIN).max(1.0);
+    [input.clamp(0.0, 1.0) as f32, output.clamp(0.0, 1.0) as f32]
+}
+
+// Draw the tone curve over the histogram of the tones it maps
+fn draw_curve(
+    area: &DrawingArea,
+    cr: &cairo::Context,
+    width: i32,
+    height: i32,
+    tone: &Tone,
+    histogram: Option<&[u32; 256]>,
+) {
+    let (width, height) = (width as f64, height as f64);
+    let fg = area
+        .style_context()
+        .lookup_color("window_fg_color")
+        .unwrap_or(gtk4::gdk::RGBA::new(0.5, 0.5, 0.5, 1.0));
+    let accent = area
+        .style_context()
+        .lookup_color("accent_color")
+        .unwrap_or(gtk4::gdk::RGBA::new(0.2, 0.5, 0.9, 1.0));
+    let set_color = |color: &gtk4::gdk::RGBA, alpha: f64| {
+        cr.set_source_rgba(
+            color.red() as f64,
+            color.green() as f64,
+            color.blue() as f64,
+            color.alpha() as f64 * alpha,
+        );
+    };
+
+    // Square roots keep a few huge bins from flattening the rest
+    if let Some(histogram) = histogram {
+        let peak = histogram.iter().copied().max().unwrap_or(0).max(1) as f64;
+        let bar = (width - 2.0 * CURVE_MARGIN) / histogram.len() as f64;
+        set_color(&fg, 0.15);
+        for (i, &count) in histogram.iter().enumerate() {
+            let bar_height = (count as f64 / peak).sqrt() * (height - 2.0 * CURVE_MARGIN);
+            cr.rectangle(
+                CURVE_MARGIN + i as f64 * bar,
+                height - CURVE_MARGIN - bar_height,
+                bar.ceil(),
+                bar_height,
+            );
+        }
+        cr.fill().unwrap();
+    }
+
+    // Quarter grid and the unchanged diagonal
+    set_color(&fg, 0.15);
+    cr.set_line_width(1.0);
+    for quarter in 0..=4 {
+        let t = quarter as f32 / 4.0;
+        let [x, _] = curve_to_area([t, 0.0], width, height);
+        let [_, y] = curve_to_area([0.0, t], width, height);
+        cr.move_to(x, CURVE_MARGIN);
+        cr.line_to(x, height - CURVE_MARGIN);
+        cr.move_to(CURVE_MARGIN, y);
+        cr.line_to(width - CURVE_MARGIN, y);
+    }
+    cr.stroke().unwrap();
+    set_color(&fg, 0.3);
+    cr.set_dash(&[4.0, 4.0], 0.0);
+    let [x0, y0] = curve_to_area([0.0, 0.0], width, height);
+    let [x1, y1] = curve_to_area([1.0, 1.0], width, height);
+    cr.move_to(x0, y0);
+    cr.line_to(x1, y1);
+    cr.stroke().unwrap();
+    cr.set_dash(&[], 0.0);
+
+    set_color(&accent, 1.0);
+    cr.set_line_width(2.0);
+    let steps = (width as usize).max(2);
+    for step in 0..=steps {
+        let input = step as f32 / steps as f32;
+        let [x, y] = curve_to_area([input, tone.curve(input)], width, height);
+        if step == 0 {
+            cr.move_to(x, y);
+        } else {
+            cr.line_to(x, y);
         }
     }
+    cr.stroke().unwrap();
+
+    for &point in &tone.curve {
+        let [x, y] = curve_to_area(point, width, height);
+        cr.arc(x, y, 4.0, 0.0, std::f64::consts::TAU);
+        cr.fill().unwrap();
+    }
+}
 
-    // When writing faces, we need to change the winding order to maintain correct face orientation
-    for y in 0..height - 1 {
-        for x in 0..width - 1 {
-            let v1 = y * width + x + 1;
-            let v2 = y * width + x + 2;
-            let v3 = (y + 1) * width + x + 2;
-            let v4 = (y + 1) * width + x + 1;
-            // Reverse the order of vertices to maintain correct face orientation
-            writeln!(file, "f {} {} {} {}", v1, v4, v3, v2)?;
+// Minimum region for speckle cleanup in pixels, from an area in one of the
+// REGION_UNITS; square millimetres follow the model's width
+fn min_region_px(area: f64, unit: u32, width_mm: f32, img: Option<&DynamicImage>) -> usize {
+    match (unit, img) {
+        (REGION_MM2, Some(img)) => {
+            let pixel_mm = width_mm as f64 / img.width().max(1) as f64;
+            (area / (pixel_mm * pixel_mm)).ceil() as usize
         }
+        (REGION_MM2, None) => 0,
+        _ => area.round() as usize,
+    }
+}
+
+// Build the image's exact depths again if the settings changed since last time
+fn refresh_depth(
+    cache: &RefCell<Option<(DepthMapBuilder, DepthMap)>>,
+    img: &DynamicImage,
+    settings: DepthMapBuilder,
+) {
+    let mut cache = cache.borrow_mut();
+    if cache.as_ref().map(|(cached, _)| cached) != Some(&settings) {
+        let depth = settings.clone().continuous(true).build(img);
+        *cache = Some((settings, depth));
     }
+}
+
+// Position in the threshold editor of a depth in 0.0..=1.0
+fn threshold_to_area(depth: f32, width: f64) -> f64 {
+    CURVE_MARGIN + depth as f64 * (width - 2.0 * CURVE_MARGIN)
+}
 
-    Ok(())
+// Depth under a position in the threshold editor
+fn area_to_threshold(x: f64, width: f64) -> f32 {
+    ((x - CURVE_MARGIN) / (width - 2.0 * CURVE_MARGIN).max(1.0)).clamp(0.0, 1.0) as f32
+}
+
+// Draw the histogram of a depth map's exact depths and its layer boundaries,
+// highlighted when they can be dragged
+fn draw_thresholds(
+    area: &DrawingArea,
+    cr: &cairo::Context,
+    width: i32,
+    height: i32,
+    depth: &DepthMap,
+    draggable: bool,
+) {
+    let (width, height) = (width as f64, height as f64);
+    let fg = area
+        .style_context()
+        .lookup_color("window_fg_color")
+        .unwrap_or(gtk4::gdk::RGBA::new(0.5, 0.5, 0.5, 1.0));
+    let accent = area
+        .style_context()
+        .lookup_color("accent_color")
+        .unwrap_or(gtk4::gdk::RGBA::new(0.2, 0.5, 0.9, 1.0));
+    let set_color = |color: &gtk4::gdk::RGBA, alpha: f64| {
+        cr.set_source_rgba(
+            color.red() as f64,
+            color.green() as f64,
+            color.blue() as f64,
+            color.alpha() as f64 * alpha,
+        );
+    };
+
+    let mut histogram = [0u32; 256];
+    for y in 0..depth.height {
+        for x in 0..depth.width {
+            if depth.opaque(x, y) {
+                histogram[(depth.fraction(x, y) * 255.0).round() as usize] += 1;
+            }
+        }
+    }
+
+    // Square roots keep a few huge bins from flattening the rest
+    let peak = histogram.iter().copied().max().unwrap_or(0).max(1) as f64;
+    let bar = (width - 2.0 * CURVE_MARGIN) / histogram.len() as f64;
+    set_color(&fg, 0.25);
+    for (i, &count) in histogram.iter().enumerate() {
+        let bar_height = (count as f64 / peak).sqrt() * (height - 2.0 * CURVE_MARGIN);
+        cr.rectangle(
+            CURVE_MARGIN + i as f64 * bar,
+            height - CURVE_MARGIN - bar_height,
+            bar.ceil(),
+            bar_height,
+        );
+    }
+    cr.fill().unwrap();
+
+    match draggable {
+        true => set_color(&accent, 1.0),
+        false => set_color(&fg, 0.6),
+    }
+    cr.set_line_width(if draggable { 2.0 } else { 1.0 });
+    for &threshold in depth.thresholds() {
+        let x = threshold_to_area(threshold, width);
+        cr.move_to(x, CURVE_MARGIN);
+        cr.line_to(x, height - CURVE_MARGIN);
+    }
+    cr.stroke().unwrap();
+    if draggable {
+        for &threshold in depth.thresholds() {
+            let x = threshold_to_area(threshold, width);
+            cr.arc(x, CURVE_MARGIN, 4.0, 0.0, std::f64::consts::TAU);
+            cr.fill().unwrap();
+        }
+    }
+}
+
+// Paint the software rendering of a mesh over the whole preview
+fn draw_model(
+    cr: &cairo::Context,
+    mesh: &Mesh,
+    camera: &Camera,
+    width: i32,
+    height: i32,
+    background: [u8; 3],
+) {
+    let rendered = camera.render(mesh, width.max(1) as u32, height.max(1) as u32, background);
+    let mut surface = cairo::ImageSurface::create(
+        cairo::Format::Rgb24,
+        rendered.width() as i32,
+        rendered.height() as i32,
+    )
+    .unwrap();
+
+    {
+        let stride = surface.stride() as usize;
+        let mut data = surface.data().unwrap();
+        for (x, y, pixel) in rendered.enumerate_pixels() {
+            let [r, g, b] = pixel.0;
+            // Cairo stores RGB24 pixels as little-endian BGRx
+            let di = y as usize * stride + x as usize * 4;
+            data[di] = b;
+            data[di + 1] = g;
+            data[di + 2] = r;
+        }
+    }
+
+    cr.set_source_surface(&surface, 0.0, 0.0).unwrap();
+    cr.paint().unwrap();
+}
+
+// Replace the rows under `expander` with one colour button and transmission
+// distance per filament in the plan
+fn fill_filament_rows(
+    expander: &adw::ExpanderRow,
+    rows: &Rc<RefCell<Vec<adw::ActionRow>>>,
+    plan: &Rc<RefCell<FilamentPlan>>,
+    preview_area: &DrawingArea,
+) {
+    for row in rows.borrow_mut().drain(..) {
+        expander.remove(&row);
+    }
+
+    for (level, filament) in plan.borrow().filaments.iter().enumerate() {
+        let row = adw::ActionRow::builder()
+            .title(format!("Layer {}", level + 1))
+            .build();
+
+        let [r, g, b] = filament.color.map(|c| c as f32 / 255.0);
+        let color_button = gtk4::ColorDialogButton::new(Some(gtk4::ColorDialog::new()));
+        color_button.set_rgba(&gtk4::gdk::RGBA::new(r, g, b, 1.0));
+        color_button.set_valign(gtk4::Align::Center);
+
+        let transmission = gtk4::SpinButton::with_range(0.1, 10.0, 0.1);
+        transmission.set_digits(1);
+        transmission.set_value(filament.transmission_mm as f64);
+        transmission.set_tooltip_text(Some("Transmission distance (mm)"));
+        transmission.set_valign(gtk4::Align::Center);
+
+        row.add_suffix(&color_button);
+        row.add_suffix(&transmission);
+
+        {
+            let plan = plan.clone();
+            let preview_area = preview_area.clone();
+            color_button.connect_rgba_notify(move |button| {
+                let rgba = button.rgba();
+                let color = [rgba.red(), rgba.green(), rgba.blue()]
+                    .map(|c| (c * 255.0).round().clamp(0.0, 255.0) as u8);
+                if let Some(filament) = plan.borrow_mut().filaments.get_mut(level) {
+                    filament.color = color;
+                }
+                preview_area.queue_draw();
+            });
+        }
+
+        {
+            let plan = plan.clone();
+            let preview_area = preview_area.clone();
+            transmission.connect_value_changed(move |s| {
+                if let Some(filament) = plan.borrow_mut().filaments.get_mut(level) {
+                    filament.transmission_mm = s.value() as f32;
+                }
+                preview_area.queue_draw();
+            });
+        }
+
+        expander.add_row(&row);
+        rows.borrow_mut().push(row);
+    }
 }
@@ -0,0 +1,356 @@
+mod grid;
+mod lithophane;
+mod quadtree;
+mod slab;
+mod terrace;
+mod wrap;
+
+pub use lithophane::{Curve, Lithophane};
+pub use wrap::Projection;
+
+use crate::depth::DepthMap;
+use grid::Grid;
+use std::collections::HashMap;
+
+/// Triangle mesh shared by every exporter. Triangles wind counter-clockwise
+/// when seen from outside, and `uvs` holds one texture coordinate per position.
+#[derive(Clone, Debug, Default)]
+pub struct Mesh {
+    pub positions: Vec<[f32; 3]>,
+    pub uvs: Vec<[f32; 2]>,
+    pub triangles: Vec<[u32; 3]>,
+    /// Depth layer of each triangle when the mesh is split into a body per
+    /// layer; empty otherwise
+    pub layers: Vec<u8>,
+}
+
+/// Physical size of the exported model. The depth runs along Y and follows the
+/// image's aspect ratio; Z is the lowest layer's thickness plus the relief.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Dimensions {
+    pub width_mm: f32,
+    pub relief_mm: f32,
+    pub min_thickness_mm: f32,
+}
+
+impl Default for Dimensions {
+    fn default() -> Self {
+        Dimensions {
+            width_mm: 100.0,
+            relief_mm: 3.0,
+            min_thickness_mm: 1.0,
+        }
+    }
+}
+
+impl Dimensions {
+    /// Height in millimetres that keeps the aspect ratio of a `width` x `height` image
+    pub fn height_mm(&self, width: u32, height: u32) -> f32 {
+        self.width_mm * height as f32 / width.max(1) as f32
+    }
+
+    /// Top surface height of a quantization level
+    pub fn layer_z(&self, level: u8, layers: u8) -> f32 {
+        self.depth_z(level as f32 / (layers as f32 - 1.0))
+    }
+
+    /// Surface height at a depth between 0.0 (lowest layer) and 1.0 (highest)
+    pub fn depth_z(&self, fraction: f32) -> f32 {
+        fraction * self.relief_mm + self.min_thickness_mm
+    }
+
+    /// Distance between neighbouring vertices when `count` of them span the width
+    pub fn pitch(&self, count: usize) -> f32 {
+        self.width_mm / count.saturating_sub(1).max(1) as f32
+    }
+}
+
+/// How the surface between neighbouring pixels is built
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MeshStyle {
+    /// One vertex per pixel, so layer boundaries become slopes
+    #[default]
+    Smooth,
+    /// Flat plateaus per layer with vertical steps between them
+    Terraced,
+}
+
+impl MeshStyle {
+    pub const ALL: [MeshStyle; 2] = [MeshStyle::Smooth, MeshStyle::Terraced];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            MeshStyle::Smooth => "Smooth",
+            MeshStyle::Terraced => "Terraced",
+        }
+    }
+}
+
+/// Everything besides the depth map that shapes the exported mesh
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MeshOptions {
+    pub style: MeshStyle,
+    pub solid: bool,
+    pub dimensions: Dimensions,
+    /// Merge flat regions into larger faces
+    pub simplify: bool,
+    /// Largest height error, in millimetres, a merged smooth face may introduce
+    pub tolerance_mm: f32,
+    /// Surface to wrap the relief around; anything but flat uses the smooth
+    /// style and keeps cut-out pixels
+    pub projection: Projection,
+    /// Build a separate closed, terraced body per layer for multi-material
+    /// printing, whatever the style and solid flag say
+    pub split_layers: bool,
+    /// Build a lithophane instead, ignoring the style, projection, solid flag,
+    /// relief and cut-out pixels
+    pub lithophane: Option<Lithophane>,
+}
+
+impl Default for MeshOptions {
+    fn default() -> Self {
+        MeshOptions {
+            style: MeshStyle::default(),
+            solid: false,
+            dimensions: Dimensions::default(),
+            simplify: true,
+            tolerance_mm: 0.0,
+            projection: Projection::default(),
+            split_layers: false,
+            lithophane: None,
+        }
+    }
+}
+
+impl Mesh {
+    /// Build the mesh for a depth map
+    pub fn build(depth: &DepthMap, options: &MeshOptions) -> Self {
+        let tolerance = options.simplify.then_some(options.tolerance_mm);
+        if let Some(lithophane) = &options.lithophane {
+            return Self::lithophane(depth, options.dimensions.width_mm, lithophane, tolerance);
+        }
+        if options.projection != Projection::Flat {
+            return Self::wrapped(
+                depth,
+                &options.dimensions,
+                options.projection,
+                options.solid,
+                tolerance,
+            );
+        }
+
+        if options.split_layers {
+            let mut mesh = Mesh::default();
+            for (level, body) in Self::layer_bodies(depth, &options.dimensions, options.simplify) {
+                mesh.append(body, level);
+            }
+            return mesh;
+        }
+
+        match options.style {
+            MeshStyle::Smooth => {
+                let mut mesh = Self::smooth(depth, &options.dimensions, tolerance);
+                if options.solid {
+                    mesh.solidify(0.0);
+                }
+                mesh
+            }
+            MeshStyle::Terraced => {
+                Self::terraced(depth, &options.dimensions, options.solid, options.simplify)
+            }
+        }
+    }
+
+    /// Height field with one vertex per pixel. With a `tolerance`, blocks of
+    /// pixels that stay within it of a flat patch are merged. Pixels cut out of
+    /// the depth map leave holes.
+    pub fn smooth(depth: &DepthMap, dimensions: &Dimensions, tolerance: Option<f32>) -> Self {
+        let (width, height) = (depth.width, depth.height);
+        let heights = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| dimensions.depth_z(depth.fraction(x, y)))
+            .collect();
+
+        let grid = Grid {
+            width,
+            height,
+            heights,
+            pitch: dimensions.pitch(width),
+            offset: (0, 0),
+            image: (width, height),
+            radius: None,
+            wrap: false,
+            mask: depth.is_masked().then(|| {
+                (0..height)
+                    .flat_map(|y| (0..width).map(move |x| depth.opaque(x, y)))
+                    .collect()
+            }),
+        };
+        grid.triangulate(tolerance)
+    }
+
+    /// Add another mesh's triangles, tagged with depth layer `level`
+    pub fn append(&mut self, other: Mesh, level: u8) {
+        let offset = self.positions.len() as u32;
+        self.positions.extend(other.positions);
+        self.uvs.extend(other.uvs);
+        self.triangles
+            .extend(other.triangles.iter().map(|t| t.map(|i| i + offset)));
+        self.layers.resize(self.triangles.len(), level);
+    }
+
+    /// Close the height field into a printable solid: a flat bottom at `floor`
+    /// and walls stitched down to it from every open edge
+    pub fn solidify(&mut self, floor: f32) {
+        let boundary = self.boundary_edges();
+        let count = self.positions.len() as u32;
+
+        // Bottom vertices sit directly below their top counterparts
+        for i in 0..count as usize {
+            let [x, y, _] = self.positions[i];
+            self.positions.push([x, y, floor]);
+            self.uvs.push(self.uvs[i]);
+        }
+
+        // The bottom mirrors the top's triangulation, wound to face down
+        for i in 0..self.triangles.len() {
+            let [a, b, c] = self.triangles[i];
+            self.triangles.push([a + count, c + count, b + count]);
+        }
+
+        // Open edges run counter-clockwise around the top, so the walls face outward
+        for [a, b] in boundary {
+            self.triangles.push([a, a + count, b + count]);
+            self.triangles.push([a, b + count, b]);
+        }
+    }
+
+    // Edges used by a single triangle, in that triangle's winding order
+    fn boundary_edges(&self) -> Vec<[u32; 2]> {
+        // Interior edges are seen twice and cancel out, so the map stays small
+        let mut open: HashMap<(u32, u32), [u32; 2]> = HashMap::new();
+        for &[a, b, c] in &self.triangles {
+            for [from, to] in [[a, b], [b, c], [c, a]] {
+                let key = (from.min(to), from.max(to));
+                if open.remove(&key).is_none() {
+                    open.insert(key, [from, to]);
+                }
+            }
+        }
+        open.into_values().collect()
+    }
+
+    /// Unit normal of a triangle following its winding
+    pub fn facet_normal(&self, triangle: [u32; 3]) -> [f32; 3] {
+        let [a, b, c] = triangle.map(|i| self.positions[i as usize]);
+        let u = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+        let v = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+        let n = [
+            u[1] * v[2] - u[2] * v[1],
+            u[2] * v[0] - u[0] * v[2],
+            u[0] * v[1] - u[1] * v[0],
+        ];
+        let len = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+        if len > 0.0 {
+            [n[0] / len, n[1] / len, n[2] / len]
+        } else {
+            [0.0, 0.0, 0.0]
+        }
+    }
+
+    /// Unit normal at every vertex, averaged over the triangles around it and
+    /// weighted by their area
+    pub fn vertex_normals(&self) -> Vec<[f32; 3]> {
+        let mut normals = vec![[0.0f32; 3]; self.positions.len()];
+        for &triangle in &self.triangles {
+            let [a, b, c] = triangle.map(|i| self.positions[i as usize]);
+            let u = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+            let v = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+            // Twice the area, pointing along the facet normal
+            let n = [
+                u[1] * v[2] - u[2] * v[1],
+                u[2] * v[0] - u[0] * v[2],
+                u[0] * v[1] - u[1] * v[0],
+            ];
+            for i in triangle {
+                for axis in 0..3 {
+                    normals[i as usize][axis] += n[axis];
+                }
+            }
+        }
+
+        for n in &mut normals {
+            let len = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+            *n = if len > 0.0 {
+                [n[0] / len, n[1] / len, n[2] / len]
+            } else {
+                [0.0, 0.0, 1.0]
+            };
+        }
+        normals
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::depth::DepthMapBuilder;
+    use image::{DynamicImage, Rgba, RgbaImage};
+
+    // Depth map from rows of layer digits, with '.' for cut-out pixels
+    pub(super) fn depth_map(rows: &[&str], layers: u8) -> DepthMap {
+        let (width, height) = (rows[0].len() as u32, rows.len() as u32);
+        let img = RgbaImage::from_fn(width, height, |x, y| {
+            match rows[y as usize].as_bytes()[x as usize] {
+                b'.' => Rgba([0, 0, 0, 0]),
+                digit => {
+                    let gray = ((digit - b'0') as f32 / (layers - 1) as f32 * 255.0).round() as u8;
+                    Rgba([gray, gray, gray, 255])
+                }
+            }
+        });
+        DepthMapBuilder::new()
+            .layers(layers)
+            .cut_out(true)
+            .build(&DynamicImage::ImageRgba8(img))
+    }
+
+    // Closed and manifold: every directed edge is used once and its reverse once
+    pub(super) fn assert_closed(mesh: &Mesh) {
+        assert!(!mesh.triangles.is_empty());
+        let mut edges: HashMap<(u32, u32), usize> = HashMap::new();
+        for &[a, b, c] in &mesh.triangles {
+            for edge in [(a, b), (b, c), (c, a)] {
+                *edges.entry(edge).or_default() += 1;
+            }
+        }
+        for (&(from, to), &count) in &edges {
+            let [p, q] = [from, to].map(|i| mesh.positions[i as usize]);
+            assert_eq!(count, 1, "edge {p:?} -> {q:?} used {count} times");
+            assert!(
+                edges.contains_key(&(to, from)),
+                "edge {p:?} -> {q:?} has no opposite"
+            );
+        }
+    }
+
+    #[test]
+    fn solidified_height_fields_are_closed() {
+        let cases: [&[&str]; 5] = [
+            &["00", "00"],
+            &["01", "10"],
+            &["0123", "1230", "2301", "3012"],
+            // Holes, including opaque patches that touch only diagonally
+            &["233.", "2310", ".011", "..13"],
+            &["00..", "00..", "..11", "..11"],
+        ];
+        for rows in cases {
+            let depth = depth_map(rows, 4);
+            for tolerance in [None, Some(0.0), Some(1.0)] {
+                let mut mesh = Mesh::smooth(&depth, &Dimensions::default(), tolerance);
+                mesh.solidify(0.0);
+                assert_closed(&mesh);
+            }
+        }
+    }
+}
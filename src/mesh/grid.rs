@@ -0,0 +1,163 @@
+use super::Mesh;
+use super::quadtree;
+
+// Heights on a regular grid of vertices, one per pixel. The image may sit inside
+// a larger grid, e.g. when a frame surrounds it, in which case `offset` is the
+// grid position of its top-left pixel. A grid that will be bent around a cylinder
+// of `radius` only merges blocks whose chords stay close to the curve, and with
+// `wrap` its last column is stitched to the first to close the tube. With a
+// `mask`, only cells whose four vertices are all set get faces.
+pub(super) struct Grid {
+    pub width: usize,
+    pub height: usize,
+    pub heights: Vec<f32>,
+    pub pitch: f32,
+    pub offset: (usize, usize),
+    pub image: (usize, usize),
+    pub radius: Option<f32>,
+    pub wrap: bool,
+    pub mask: Option<Vec<bool>>,
+}
+
+impl Grid {
+    fn z(&self, x: usize, y: usize) -> f32 {
+        self.heights[y * self.width + x]
+    }
+
+    // Whether every vertex of a block is set in the mask, or none is
+    fn covered(&self, block: quadtree::Block, set: bool) -> bool {
+        let Some(mask) = &self.mask else {
+            return set;
+        };
+        let [(x0, y0), _, (x1, y1), _] = block.corners();
+        (y0..=y1).all(|y| {
+            mask[y * self.width + x0..=y * self.width + x1]
+                .iter()
+                .all(|&m| m == set)
+        })
+    }
+
+    // Triangulate the grid. With a `tolerance`, blocks that stay within it of a
+    // flat patch are merged.
+    pub fn triangulate(&self, tolerance: Option<f32>) -> Mesh {
+        let (width, height) = (self.width, self.height);
+        let wrap = self.wrap && width > 1;
+
+        // Vertices sit on pixels, so the quadtree cells lie between them
+        let cells_x = width.saturating_sub(1);
+        let cells_y = height.saturating_sub(1);
+        let blocks = quadtree::leaves(cells_x, cells_y, |block| {
+            // Cut-out areas merge freely, as they are dropped anyway
+            if self.covered(block, false) {
+                return true;
+            }
+            let Some(tolerance) = tolerance else {
+                return false;
+            };
+            if !self.covered(block, true) {
+                return false;
+            }
+            // Bending lifts a flat block's middle off the curve by its sagitta
+            if let Some(radius) = self.radius {
+                let span = block.size as f32 * self.pitch;
+                if span * span / (8.0 * radius) > tolerance {
+                    return false;
+                }
+            }
+            let [(x0, y0), _, (x1, y1), _] = block.corners();
+            let (z00, z10, z01, z11) = (
+                self.z(x0, y0),
+                self.z(x1, y0),
+                self.z(x0, y1),
+                self.z(x1, y1),
+            );
+            // A twisted patch can't be covered by flat triangles
+            if (z00 + z11 - z10 - z01).abs() / 4.0 > tolerance {
+                return false;
+            }
+            (y0..=y1).all(|y| {
+                let t = (y - y0) as f32 / block.size as f32;
+                (x0..=x1).all(|x| {
+                    let s = (x - x0) as f32 / block.size as f32;
+                    let expected =
+                        (z00 * (1.0 - s) + z10 * s) * (1.0 - t) + (z01 * (1.0 - s) + z11 * s) * t;
+                    (self.z(x, y) - expected).abs() <= tolerance
+                })
+            })
+        });
+        let blocks: Vec<_> = blocks
+            .into_iter()
+            .filter(|&block| self.covered(block, true))
+            .collect();
+
+        let mut mesh = Mesh::default();
+        let mut indices = vec![u32::MAX; width * height];
+        let vertex = |mesh: &mut Mesh, indices: &mut [u32], x: usize, y: usize| {
+            let index = &mut indices[y * width + x];
+            if *index == u32::MAX {
+                *index = mesh.positions.len() as u32;
+                // Negate the Y coordinate to rotate 180 degrees around X axis
+                mesh.positions.push([
+                    x as f32 * self.pitch,
+                    -(y as f32 * self.pitch),
+                    self.z(x, y),
+                ]);
+                mesh.uvs.push(self.uv(x, y));
+            }
+            *index
+        };
+
+        // Every block corner is a vertex; edges only pick up the corners of smaller neighbours
+        for block in &blocks {
+            for (x, y) in block.corners() {
+                vertex(&mut mesh, &mut indices, x, y);
+            }
+        }
+        // The seam strip has a vertex on every row of both edge columns
+        if wrap {
+            for y in 0..height {
+                vertex(&mut mesh, &mut indices, 0, y);
+                vertex(&mut mesh, &mut indices, width - 1, y);
+            }
+        }
+
+        let mut triangles = Vec::with_capacity(2 * blocks.len());
+        let mut points = Vec::new();
+        for block in &blocks {
+            points.clear();
+            points.extend(
+                block
+                    .perimeter()
+                    .map(|(x, y)| indices[y * width + x])
+                    .filter(|&index| index != u32::MAX),
+            );
+            let (cx, cy) = block.center();
+            quadtree::fan(
+                &points,
+                || vertex(&mut mesh, &mut indices, cx, cy),
+                &mut triangles,
+            );
+        }
+
+        if wrap {
+            for y in 0..cells_y {
+                let [tl, bl] = [y, y + 1].map(|y| indices[y * width + width - 1]);
+                let [tr, br] = [y, y + 1].map(|y| indices[y * width]);
+                triangles.push([tl, bl, br]);
+                triangles.push([tl, br, tr]);
+            }
+        }
+        mesh.triangles = triangles;
+
+        mesh
+    }
+
+    // Sample each vertex's pixel centre, clamped to the image for frame vertices;
+    // v = 0 is the bottom of the image
+    fn uv(&self, x: usize, y: usize) -> [f32; 2] {
+        let (image_width, image_height) = (self.image.0.max(1) as f32, self.image.1.max(1) as f32);
+        let u = (x as f32 - self.offset.0 as f32 + 0.5) / image_width;
+        let v = (y as f32 - self.offset.1 as f32 + 0.5) / image_height;
+        [u.clamp(0.0, 1.0), 1.0 - v.clamp(0.0, 1.0)]
+    }
+}
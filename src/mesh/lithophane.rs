@@ -0,0 +1,127 @@
+use super::Mesh;
+use super::grid::Grid;
+use crate::depth::DepthMap;
+use std::f32::consts::TAU;
+
+/// Shape of a lithophane panel
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Curve {
+    #[default]
+    Flat,
+    /// Bent around a vertical axis through [`Lithophane::arc_degrees`]
+    Arc,
+    /// Closed all the way around, as for a lamp shade
+    Cylinder,
+}
+
+impl Curve {
+    pub const ALL: [Curve; 3] = [Curve::Flat, Curve::Arc, Curve::Cylinder];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Curve::Flat => "Flat",
+            Curve::Arc => "Arc",
+            Curve::Cylinder => "Cylinder",
+        }
+    }
+}
+
+/// A backlit panel whose darker pixels are thicker, so less light passes through
+/// them. The frame surrounds the image at the full thickness.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Lithophane {
+    /// Thickness behind the brightest layer
+    pub min_thickness_mm: f32,
+    /// Thickness behind the darkest layer and of the frame
+    pub max_thickness_mm: f32,
+    /// Width of the frame around the image; a cylinder only gets it top and bottom
+    pub frame_mm: f32,
+    pub curve: Curve,
+    /// Angle an arc-curved panel spans
+    pub arc_degrees: f32,
+}
+
+impl Default for Lithophane {
+    fn default() -> Self {
+        Lithophane {
+            min_thickness_mm: 0.8,
+            max_thickness_mm: 3.0,
+            frame_mm: 3.0,
+            curve: Curve::default(),
+            arc_degrees: 120.0,
+        }
+    }
+}
+
+impl Mesh {
+    /// Solid lithophane panel `width_mm` wide, not counting the frame. Curved
+    /// panels stand upright around the Z axis with the relief facing outward;
+    /// flat ones lie on their back.
+    pub fn lithophane(
+        depth: &DepthMap,
+        width_mm: f32,
+        lithophane: &Lithophane,
+        tolerance: Option<f32>,
+    ) -> Self {
+        let pitch = width_mm / depth.width.saturating_sub(1).max(1) as f32;
+        let frame = (lithophane.frame_mm.max(0.0) / pitch).round() as usize;
+        let wrap = lithophane.curve == Curve::Cylinder;
+        let offset = (if wrap { 0 } else { frame }, frame);
+        let (width, height) = (depth.width + 2 * offset.0, depth.height + 2 * offset.1);
+
+        let (thin, thick) = (lithophane.min_thickness_mm, lithophane.max_thickness_mm);
+        let heights = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let (x, y) = (x.wrapping_sub(offset.0), y.wrapping_sub(offset.1));
+                if x < depth.width && y < depth.height {
+                    thick - depth.fraction(x, y) * (thick - thin)
+                } else {
+                    thick
+                }
+            })
+            .collect();
+
+        // A cylinder's circumference includes the seam between its last and first column
+        let (span, angle) = match lithophane.curve {
+            Curve::Flat => (0.0, 0.0),
+            Curve::Arc => (
+                width.saturating_sub(1) as f32 * pitch,
+                lithophane.arc_degrees.clamp(1.0, 359.0).to_radians(),
+            ),
+            Curve::Cylinder => (width as f32 * pitch, TAU),
+        };
+        let radius = (angle > 0.0).then(|| span / angle);
+
+        let grid = Grid {
+            width,
+            height,
+            heights,
+            pitch,
+            offset,
+            image: (depth.width, depth.height),
+            radius,
+            wrap,
+            mask: None,
+        };
+        let mut mesh = grid.triangulate(tolerance);
+        mesh.solidify(0.0);
+
+        if let Some(radius) = radius {
+            let top = height.saturating_sub(1) as f32 * pitch;
+            // Arcs are centred on the Y axis, with their middle touching the origin
+            let (start, centre) = match lithophane.curve {
+                Curve::Arc => (span / 2.0, radius),
+                _ => (0.0, 0.0),
+            };
+            for position in &mut mesh.positions {
+                let [x, y, z] = *position;
+                let phi = (x - start) / radius;
+                let r = radius + z;
+                *position = [r * phi.sin(), centre - r * phi.cos(), y + top];
+            }
+        }
+
+        mesh
+    }
+}
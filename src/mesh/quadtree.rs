@@ -0,0 +1,93 @@
+// Square block of cells covering [x, x + size) x [y, y + size)
+#[derive(Clone, Copy, Debug)]
+pub(super) struct Block {
+    pub x: usize,
+    pub y: usize,
+    pub size: usize,
+}
+
+impl Block {
+    // Corner positions along the block's edge, starting at the top-left and running
+    // down the left side first, which is counter-clockwise once Y is flipped
+    pub fn perimeter(self) -> impl Iterator<Item = (usize, usize)> {
+        let Block { x, y, size } = self;
+        let left = (0..size).map(move |i| (x, y + i));
+        let bottom = (0..size).map(move |i| (x + i, y + size));
+        let right = (0..size).map(move |i| (x + size, y + size - i));
+        let top = (0..size).map(move |i| (x + size - i, y));
+        left.chain(bottom).chain(right).chain(top)
+    }
+
+    pub fn corners(self) -> [(usize, usize); 4] {
+        let Block { x, y, size } = self;
+        [(x, y), (x, y + size), (x + size, y + size), (x + size, y)]
+    }
+
+    pub fn center(self) -> (usize, usize) {
+        (self.x + self.size / 2, self.y + self.size / 2)
+    }
+
+    // The cell of the block next to a point on its perimeter
+    pub fn owner(self, (cx, cy): (usize, usize)) -> (usize, usize) {
+        let last = self.size - 1;
+        (
+            cx.clamp(self.x, self.x + last),
+            cy.clamp(self.y, self.y + last),
+        )
+    }
+}
+
+// Cover a `width` x `height` grid of cells with the largest aligned squares for
+// which `uniform` holds. Single cells are always accepted.
+pub(super) fn leaves(
+    width: usize,
+    height: usize,
+    mut uniform: impl FnMut(Block) -> bool,
+) -> Vec<Block> {
+    let mut size = 1;
+    while size < width.max(height) {
+        size *= 2;
+    }
+
+    let mut leaves = Vec::new();
+    let mut stack = vec![Block { x: 0, y: 0, size }];
+
+    while let Some(block) = stack.pop() {
+        if block.x >= width || block.y >= height {
+            continue;
+        }
+
+        let inside = block.x + block.size <= width && block.y + block.size <= height;
+        if block.size == 1 || (inside && uniform(block)) {
+            leaves.push(block);
+            continue;
+        }
+
+        let half = block.size / 2;
+        for (dx, dy) in [(0, 0), (half, 0), (0, half), (half, half)] {
+            stack.push(Block {
+                x: block.x + dx,
+                y: block.y + dy,
+                size: half,
+            });
+        }
+    }
+
+    leaves
+}
+
+// Triangulate a block from the vertices found along its perimeter. Neighbouring
+// smaller blocks leave extra points on the edges, which are fanned from the
+// centre so adjacent faces share every edge.
+pub(super) fn fan(points: &[u32], center: impl FnOnce() -> u32, triangles: &mut Vec<[u32; 3]>) {
+    if let [a, b, c, d] = *points {
+        triangles.push([a, b, c]);
+        triangles.push([a, c, d]);
+        return;
+    }
+
+    let center = center();
+    for (i, &point) in points.iter().enumerate() {
+        triangles.push([center, point, points[(i + 1) % points.len()]]);
+    }
+}
@@ -0,0 +1,224 @@
+use super::quadtree::{self, Block};
+use super::{Dimensions, Mesh};
+use crate::depth::DepthMap;
+use std::collections::HashMap;
+
+impl Mesh {
+    /// Split the terraced solid into one closed body per layer, for printers
+    /// that give each layer its own material. The base body spans the whole
+    /// image up to the lowest layer; every other layer is a slab over the pixels
+    /// at or above it, stacked on the one below. Layers without pixels are
+    /// skipped.
+    pub fn layer_bodies(
+        depth: &DepthMap,
+        dimensions: &Dimensions,
+        simplify: bool,
+    ) -> Vec<(u8, Mesh)> {
+        let (width, height) = (depth.width, depth.height);
+        let pitch = dimensions.pitch(width + 1);
+
+        (0..depth.layers)
+            .filter_map(|level| {
+                let mask: Vec<bool> = (0..height)
+                    .flat_map(|y| (0..width).map(move |x| (x, y)))
+                    .map(|(x, y)| depth.opaque(x, y) && depth.level(x, y) >= level)
+                    .collect();
+                if !mask.contains(&true) {
+                    return None;
+                }
+
+                let bottom = match level {
+                    0 => 0.0,
+                    _ => dimensions.layer_z(level - 1, depth.layers),
+                };
+                let mut slab = Slab {
+                    width,
+                    height,
+                    mask,
+                    pitch,
+                    z: [bottom, dimensions.layer_z(level, depth.layers)],
+                    vertices: HashMap::new(),
+                    mesh: Mesh::default(),
+                };
+                slab.build(simplify);
+                Some((level, slab.mesh))
+            })
+            .collect()
+    }
+}
+
+// Extrusion of the pixels in `mask` from z[0] up to z[1]. Corner vertices are
+// keyed by position, whether they're on top, and, where two pixels of the slab
+// touch only diagonally, whether they belong to the upper one, so the pinch
+// doesn't join the bodies through a shared edge.
+struct Slab {
+    width: usize,
+    height: usize,
+    mask: Vec<bool>,
+    pitch: f32,
+    z: [f32; 2],
+    vertices: HashMap<(usize, usize, bool, bool), u32>,
+    mesh: Mesh,
+}
+
+impl Slab {
+    fn cell(&self, x: isize, y: isize) -> bool {
+        x >= 0
+            && y >= 0
+            && x < self.width as isize
+            && y < self.height as isize
+            && self.mask[y as usize * self.width + x as usize]
+    }
+
+    fn build(&mut self, simplify: bool) {
+        let width = self.width;
+        let blocks: Vec<Block> = quadtree::leaves(self.width, self.height, |block| {
+            let first = self.mask[block.y * width + block.x];
+            simplify
+                && (block.y..block.y + block.size).all(|y| {
+                    self.mask[y * width + block.x..y * width + block.x + block.size]
+                        .iter()
+                        .all(|&cell| cell == first)
+                })
+        })
+        .into_iter()
+        .filter(|block| self.mask[block.y * width + block.x])
+        .collect();
+
+        // Corners and walls first, so each face can pick up every vertex on its edges
+        for &block in &blocks {
+            for corner in block.corners() {
+                let pixel = block.owner(corner);
+                self.vertex(corner, pixel, true);
+                self.vertex(corner, pixel, false);
+            }
+        }
+        self.walls();
+        for &block in &blocks {
+            self.face(block, true);
+            self.face(block, false);
+        }
+    }
+
+    // Key of the corner `(cx, cy)` of the slab pixel `pixel`
+    fn key(
+        &self,
+        (cx, cy): (usize, usize),
+        pixel: (usize, usize),
+        top: bool,
+    ) -> (usize, usize, bool, bool) {
+        let (x, y) = (cx as isize, cy as isize);
+        let [north_west, north_east, south_west, south_east] =
+            [(x - 1, y - 1), (x, y - 1), (x - 1, y), (x, y)].map(|(x, y)| self.cell(x, y));
+        let pinch =
+            north_west == south_east && north_east == south_west && north_west != north_east;
+        (cx, cy, top, pinch && pixel.1 < cy)
+    }
+
+    fn vertex(&mut self, corner: (usize, usize), pixel: (usize, usize), top: bool) -> u32 {
+        let key = self.key(corner, pixel, top);
+        if let Some(&index) = self.vertices.get(&key) {
+            return index;
+        }
+        let (cx, cy) = corner;
+
+        let index = self.mesh.positions.len() as u32;
+        // Negate the Y coordinate to rotate 180 degrees around X axis
+        self.mesh.positions.push([
+            cx as f32 * self.pitch,
+            -(cy as f32 * self.pitch),
+            self.z[top as usize],
+        ]);
+        self.mesh.uvs.push([
+            cx as f32 / self.width as f32,
+            1.0 - cy as f32 / self.height as f32,
+        ]);
+        self.vertices.insert(key, index);
+        index
+    }
+
+    // Triangulate the top or (flipped) bottom of a block through every vertex on its edges
+    fn face(&mut self, block: Block, top: bool) {
+        let mut points: Vec<u32> = block
+            .perimeter()
+            .filter_map(|corner| {
+                let key = self.key(corner, block.owner(corner), top);
+                self.vertices.get(&key).copied()
+            })
+            .collect();
+        if !top {
+            points.reverse();
+        }
+
+        let center = block.center();
+        let mut triangles = Vec::new();
+        quadtree::fan(&points, || self.vertex(center, center, top), &mut triangles);
+        self.mesh.triangles.extend(triangles);
+    }
+
+    // A wall runs along every cell edge between a pixel of the slab and one outside it
+    fn walls(&mut self) {
+        let (width, height) = (self.width as isize, self.height as isize);
+
+        for y in 0..height {
+            for x in -1..width {
+                let (west, east) = (self.cell(x, y), self.cell(x + 1, y));
+                let (cx, top, bottom) = ((x + 1) as usize, y as usize, y as usize + 1);
+                // Seen from outside, the wall's left corner comes first
+                if west && !east {
+                    self.wall((cx - 1, top), (cx, bottom), (cx, top));
+                } else if east && !west {
+                    self.wall((cx, top), (cx, top), (cx, bottom));
+                }
+            }
+        }
+
+        for y in -1..height {
+            for x in 0..width {
+                let (north, south) = (self.cell(x, y), self.cell(x, y + 1));
+                let (cy, left, right) = ((y + 1) as usize, x as usize, x as usize + 1);
+                if north && !south {
+                    self.wall((left, cy - 1), (left, cy), (right, cy));
+                } else if south && !north {
+                    self.wall((left, cy), (right, cy), (left, cy));
+                }
+            }
+        }
+    }
+
+    // Wall of the slab pixel `pixel` between two of its corners
+    fn wall(&mut self, pixel: (usize, usize), left: (usize, usize), right: (usize, usize)) {
+        let [left_bottom, left_top] = [false, true].map(|top| self.vertex(left, pixel, top));
+        let [right_bottom, right_top] = [false, true].map(|top| self.vertex(right, pixel, top));
+        self.mesh
+            .triangles
+            .push([left_bottom, right_bottom, left_top]);
+        self.mesh
+            .triangles
+            .push([left_top, right_bottom, right_top]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{assert_closed, depth_map};
+    use super::*;
+
+    #[test]
+    fn layer_bodies_are_closed() {
+        let cases: [&[&str]; 4] = [
+            &["0"],
+            &["01", "10"],
+            &["0011", "0011", "1100", "1100"],
+            &["3.2.", ".1.0", "21..", "0.13"],
+        ];
+        for rows in cases {
+            let depth = depth_map(rows, 4);
+            for simplify in [false, true] {
+                for (_, body) in Mesh::layer_bodies(&depth, &Dimensions::default(), simplify) {
+                    assert_closed(&body);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,328 @@
+use super::quadtree::{self, Block};
+use super::{Dimensions, Mesh};
+use crate::depth::DepthMap;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+// Level key of the bottom plate, below every quantization level
+const FLOOR: i16 = -1;
+// Level key of pixels cut out of the depth map, which are left empty
+const CUT: i16 = -2;
+
+impl Mesh {
+    /// Flat plateaus at each layer's height, with vertical walls wherever
+    /// neighbouring pixels differ. In solid mode the image border steps down to a
+    /// bottom plate at z = 0. With `simplify`, runs of pixels on the same layer
+    /// share large faces. Pixels cut out of the depth map are treated like the
+    /// outside of the image.
+    pub fn terraced(
+        depth: &DepthMap,
+        dimensions: &Dimensions,
+        solid: bool,
+        simplify: bool,
+    ) -> Self {
+        let (width, height) = (depth.width, depth.height);
+        let levels: Vec<i16> = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| match depth.opaque(x, y) {
+                true => depth.level(x, y) as i16,
+                false => CUT,
+            })
+            .collect();
+
+        let plateaus = quadtree::leaves(width, height, |block| {
+            let first = levels[block.y * width + block.x];
+            simplify
+                && (block.y..block.y + block.size).all(|y| {
+                    levels[y * width + block.x..y * width + block.x + block.size]
+                        .iter()
+                        .all(|&key| key == first)
+                })
+        });
+
+        let mut builder = Terraces {
+            width,
+            height,
+            levels,
+            solid,
+            layers: depth.layers,
+            dimensions,
+            pitch: dimensions.pitch(width + 1),
+            vertices: HashMap::new(),
+            mesh: Mesh::default(),
+        };
+
+        // Corners and walls first, so each face can pick up every vertex on its edges
+        let plateaus: Vec<(Block, i16)> = plateaus
+            .into_iter()
+            .map(|block| (block, builder.levels[block.y * width + block.x]))
+            .filter(|&(_, key)| key != CUT)
+            .collect();
+        for &(block, key) in &plateaus {
+            builder.corners(block, key);
+        }
+        builder.walls();
+
+        for &(block, key) in &plateaus {
+            builder.face(block, key, false);
+        }
+
+        if solid {
+            // The bottom covers the same pixels as the plateaus
+            let cut = |x: usize, y: usize| builder.levels[y * width + x] == CUT;
+            let bottom: Vec<Block> = quadtree::leaves(width, height, |block| {
+                let first = cut(block.x, block.y);
+                simplify
+                    && (block.y..block.y + block.size)
+                        .all(|y| (block.x..block.x + block.size).all(|x| cut(x, y) == first))
+            })
+            .into_iter()
+            .filter(|block| !cut(block.x, block.y))
+            .collect();
+            for &block in &bottom {
+                builder.corners(block, FLOOR);
+            }
+            for &block in &bottom {
+                builder.face(block, FLOOR, true);
+            }
+        }
+
+        builder.mesh
+    }
+}
+
+// Pixels are cells between (width + 1) x (height + 1) corners, and each corner
+// holds one vertex per distinct level among the cells around it. Where one
+// diagonal pair of cells rises above the other, the levels in between are
+// pinched like a checkerboard, so those vertices are also keyed by whether they
+// belong to the upper cell of the pair and the two sides don't share an edge.
+struct Terraces<'a> {
+    width: usize,
+    height: usize,
+    levels: Vec<i16>,
+    solid: bool,
+    layers: u8,
+    dimensions: &'a Dimensions,
+    pitch: f32,
+    vertices: HashMap<(usize, usize, i16, bool), u32>,
+    mesh: Mesh,
+}
+
+impl Terraces<'_> {
+    // Level of a cell; outside the image and cut-out pixels are the floor in
+    // solid mode and open otherwise
+    fn cell(&self, x: isize, y: isize) -> Option<i16> {
+        let key = if x < 0 || y < 0 || x >= self.width as isize || y >= self.height as isize {
+            CUT
+        } else {
+            self.levels[y as usize * self.width + x as usize]
+        };
+        match key {
+            CUT => self.solid.then_some(FLOOR),
+            key => Some(key),
+        }
+    }
+
+    // Key of the vertex at level `key` on the corner `(cx, cy)` of the cell `pixel`
+    fn key(
+        &self,
+        (cx, cy): (usize, usize),
+        key: i16,
+        pixel: (usize, usize),
+    ) -> (usize, usize, i16, bool) {
+        let (x, y) = (cx as isize, cy as isize);
+        let cells = [(x - 1, y - 1), (x, y - 1), (x - 1, y), (x, y)].map(|(x, y)| self.cell(x, y));
+        // Whether `key` is above both cells of one diagonal and no higher than the other's
+        let pinch = match cells {
+            [
+                Some(north_west),
+                Some(north_east),
+                Some(south_west),
+                Some(south_east),
+            ] => {
+                let rises =
+                    |(a, b): (i16, i16), (c, d): (i16, i16)| a.min(b) >= key && key > c.max(d);
+                rises((north_west, south_east), (north_east, south_west))
+                    || rises((north_east, south_west), (north_west, south_east))
+            }
+            _ => false,
+        };
+        (cx, cy, key, pinch && pixel.1 < cy)
+    }
+
+    fn vertex(&mut self, corner: (usize, usize), key: i16, pixel: (usize, usize)) -> u32 {
+        let vertex_key = self.key(corner, key, pixel);
+        if let Some(&index) = self.vertices.get(&vertex_key) {
+            return index;
+        }
+        let (cx, cy) = corner;
+
+        let z = if key == FLOOR {
+            0.0
+        } else {
+            self.dimensions.layer_z(key as u8, self.layers)
+        };
+        let index = self.mesh.positions.len() as u32;
+        // Negate the Y coordinate to rotate 180 degrees around X axis
+        self.mesh
+            .positions
+            .push([cx as f32 * self.pitch, -(cy as f32 * self.pitch), z]);
+        self.mesh.uvs.push([
+            cx as f32 / self.width as f32,
+            1.0 - cy as f32 / self.height as f32,
+        ]);
+        self.vertices.insert(vertex_key, index);
+        index
+    }
+
+    // Distinct levels of the cells meeting at a corner, ascending
+    fn corner_levels(&self, cx: usize, cy: usize) -> Vec<i16> {
+        let (cx, cy) = (cx as isize, cy as isize);
+        let mut keys: Vec<i16> = [(cx - 1, cy - 1), (cx, cy - 1), (cx - 1, cy), (cx, cy)]
+            .into_iter()
+            .filter_map(|(x, y)| self.cell(x, y))
+            .collect();
+        keys.sort_unstable();
+        keys.dedup();
+        keys
+    }
+
+    fn corners(&mut self, block: Block, key: i16) {
+        for corner in block.corners() {
+            self.vertex(corner, key, block.owner(corner));
+        }
+    }
+
+    // Triangulate a flat block at level `key` through every vertex already on its
+    // edges; `downward` flips it to face the bottom
+    fn face(&mut self, block: Block, key: i16, downward: bool) {
+        let mut points: Vec<u32> = block
+            .perimeter()
+            .filter_map(|corner| {
+                let vertex_key = self.key(corner, key, block.owner(corner));
+                self.vertices.get(&vertex_key).copied()
+            })
+            .collect();
+        if downward {
+            points.reverse();
+        }
+
+        let center = block.center();
+        let mut triangles = Vec::new();
+        quadtree::fan(&points, || self.vertex(center, key, center), &mut triangles);
+        self.mesh.triangles.extend(triangles);
+    }
+
+    // A wall runs along every cell edge whose two sides sit at different levels
+    fn walls(&mut self) {
+        let (width, height) = (self.width as isize, self.height as isize);
+
+        // Edges between horizontal neighbours, on the corner column cx = x + 1
+        for y in 0..height {
+            for x in -1..width {
+                let (Some(west), Some(east)) = (self.cell(x, y), self.cell(x + 1, y)) else {
+                    continue;
+                };
+                let (cx, top, bottom) = ((x + 1) as usize, y as usize, y as usize + 1);
+                // Seen from the lower side, the wall's left corner comes first
+                match west.cmp(&east) {
+                    Ordering::Greater => {
+                        self.wall((cx - 1, top), (cx, bottom), (cx, top), east, west)
+                    }
+                    Ordering::Less => self.wall((cx, top), (cx, top), (cx, bottom), west, east),
+                    Ordering::Equal => {}
+                }
+            }
+        }
+
+        // Edges between vertical neighbours, on the corner row cy = y + 1
+        for y in -1..height {
+            for x in 0..width {
+                let (Some(north), Some(south)) = (self.cell(x, y), self.cell(x, y + 1)) else {
+                    continue;
+                };
+                let (cy, left, right) = ((y + 1) as usize, x as usize, x as usize + 1);
+                match north.cmp(&south) {
+                    Ordering::Greater => {
+                        self.wall((left, cy - 1), (left, cy), (right, cy), south, north)
+                    }
+                    Ordering::Less => self.wall((left, cy), (right, cy), (left, cy), north, south),
+                    Ordering::Equal => {}
+                }
+            }
+        }
+    }
+
+    // Stitch a vertical wall of the higher cell `pixel` between two corners from
+    // level `low` up to `high`, splitting each side at every level another face
+    // meets it so no T-junctions form
+    fn wall(
+        &mut self,
+        pixel: (usize, usize),
+        left: (usize, usize),
+        right: (usize, usize),
+        low: i16,
+        high: i16,
+    ) {
+        let column = |builder: &mut Self, (cx, cy): (usize, usize)| -> Vec<(i16, u32)> {
+            builder
+                .corner_levels(cx, cy)
+                .into_iter()
+                .filter(|key| (low..=high).contains(key))
+                .map(|key| (key, builder.vertex((cx, cy), key, pixel)))
+                .collect()
+        };
+        let left = column(self, left);
+        let right = column(self, right);
+
+        // Zip both columns upward, always advancing the side with the lower next level
+        let (mut i, mut j) = (0, 0);
+        while i + 1 < left.len() || j + 1 < right.len() {
+            let advance_left =
+                j + 1 >= right.len() || (i + 1 < left.len() && left[i + 1].0 <= right[j + 1].0);
+            if advance_left {
+                self.mesh
+                    .triangles
+                    .push([left[i].1, right[j].1, left[i + 1].1]);
+                i += 1;
+            } else {
+                self.mesh
+                    .triangles
+                    .push([left[i].1, right[j].1, right[j + 1].1]);
+                j += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{assert_closed, depth_map};
+    use super::*;
+
+    #[test]
+    fn solid_terraces_are_closed() {
+        let cases: [&[&str]; 6] = [
+            &["0"],
+            // Pinches between two levels, a pixel and a block apart
+            &["01", "10"],
+            &["0011", "0011", "1100", "1100"],
+            // Diagonal pairs that share no level
+            &["31", "24"],
+            &["0213", "3120", "1302", "2031"],
+            // Pinches against cut-out pixels, which sit on the bottom plate
+            &["3.2.", ".1.0", "20..", "0.13"],
+        ];
+        for rows in cases {
+            let depth = depth_map(rows, 5);
+            for simplify in [false, true] {
+                assert_closed(&Mesh::terraced(
+                    &depth,
+                    &Dimensions::default(),
+                    true,
+                    simplify,
+                ));
+            }
+        }
+    }
+}
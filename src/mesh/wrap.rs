@@ -0,0 +1,191 @@
+use super::grid::Grid;
+use super::{Dimensions, Mesh};
+use crate::depth::DepthMap;
+use std::collections::HashMap;
+use std::f32::consts::{FRAC_PI_2, PI, TAU};
+
+/// Surface the relief is displaced from
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Projection {
+    /// A flat plate
+    #[default]
+    Flat,
+    /// The image's width wraps once around a vertical cylinder
+    Cylinder,
+    /// Longitude follows the width and latitude the height, as for a 2:1 panorama
+    Sphere,
+    /// The upper half of a sphere, the bottom row of pixels on its rim
+    Dome,
+}
+
+impl Projection {
+    pub const ALL: [Projection; 4] = [
+        Projection::Flat,
+        Projection::Cylinder,
+        Projection::Sphere,
+        Projection::Dome,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Projection::Flat => "Flat",
+            Projection::Cylinder => "Cylinder",
+            Projection::Sphere => "Sphere",
+            Projection::Dome => "Dome",
+        }
+    }
+}
+
+impl Mesh {
+    /// Smooth relief wrapped around a cylinder, sphere or dome whose
+    /// circumference is the model width, each pixel displaced outward along the
+    /// surface normal. The wrapped seam and the poles are closed; in solid mode a
+    /// second shell at the base radius gives the surface its thickness.
+    pub fn wrapped(
+        depth: &DepthMap,
+        dimensions: &Dimensions,
+        projection: Projection,
+        solid: bool,
+        tolerance: Option<f32>,
+    ) -> Self {
+        let (width, height) = (depth.width, depth.height);
+        let pitch = dimensions.width_mm / width.max(1) as f32;
+        let radius = dimensions.width_mm / TAU;
+
+        let heights = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| dimensions.depth_z(depth.fraction(x, y)))
+            .collect();
+        let grid = Grid {
+            width,
+            height,
+            heights,
+            pitch,
+            offset: (0, 0),
+            image: (width, height),
+            radius: (projection != Projection::Flat).then_some(radius),
+            wrap: projection != Projection::Flat,
+            mask: None,
+        };
+        let mut mesh = grid.triangulate(tolerance);
+
+        // Rows of pixels sit half a row away from the poles
+        let (top, bottom) = (0.5 * pitch, -(height as f32 - 0.5) * pitch);
+        match projection {
+            Projection::Sphere => {
+                mesh.cap(top, pitch, 1.0);
+                mesh.cap(bottom, pitch, 0.0);
+            }
+            Projection::Dome => mesh.cap(top, pitch, 1.0),
+            _ => {}
+        }
+        if solid {
+            mesh.solidify(0.0);
+        }
+        if projection != Projection::Flat {
+            mesh.split_seam();
+        }
+
+        // Polar angle per unit of (negated) Y, from the north pole down
+        let latitude = match projection {
+            Projection::Sphere => PI / (height as f32 * pitch),
+            _ => FRAC_PI_2 / ((height as f32 - 0.5).max(0.5) * pitch),
+        };
+        let length = height.saturating_sub(1) as f32 * pitch;
+        for position in &mut mesh.positions {
+            let [x, y, z] = *position;
+            let phi = x / radius;
+            let r = radius + z;
+            *position = match projection {
+                Projection::Flat => [x, y, z],
+                Projection::Cylinder => [r * phi.sin(), -r * phi.cos(), y + length],
+                Projection::Sphere | Projection::Dome => {
+                    let theta = (top - y) * latitude;
+                    let ring = r * theta.sin();
+                    [ring * phi.sin(), -ring * phi.cos(), r * theta.cos()]
+                }
+            };
+        }
+
+        mesh
+    }
+
+    // Close the open row of the grid lying half a `pitch` from `y` with a fan
+    // around a pole vertex there, at the row's mean height
+    fn cap(&mut self, y: f32, pitch: f32, v: f32) {
+        let rim: Vec<[u32; 2]> = self
+            .boundary_edges()
+            .into_iter()
+            .filter(|edge| {
+                edge.iter()
+                    .all(|&i| (self.positions[i as usize][1] - y).abs() < pitch)
+            })
+            .collect();
+        if rim.is_empty() {
+            return;
+        }
+
+        let z = rim
+            .iter()
+            .map(|&[a, _]| self.positions[a as usize][2])
+            .sum::<f32>()
+            / rim.len() as f32;
+        let pole = self.positions.len() as u32;
+        self.positions.push([0.0, y, z]);
+        self.uvs.push([0.5, v]);
+        for [a, b] in rim {
+            self.triangles.push([b, a, pole]);
+        }
+    }
+
+    // Give the triangles bridging the seam, from the last column back round to
+    // the first, copies of the first column's vertices at u = 1, so their texture
+    // doesn't run backwards across the whole image. The copies sit on the same
+    // positions, which keeps the surface closed for formats without UVs.
+    fn split_seam(&mut self) {
+        let Mesh {
+            positions,
+            uvs,
+            triangles,
+            ..
+        } = self;
+        let mut copies: HashMap<u32, u32> = HashMap::new();
+        for triangle in triangles.iter_mut() {
+            let u = triangle.map(|i| uvs[i as usize][0]);
+            let (low, high) = (u[0].min(u[1]).min(u[2]), u[0].max(u[1]).max(u[2]));
+            if high - low <= 0.5 {
+                continue;
+            }
+            for i in triangle.iter_mut() {
+                if uvs[*i as usize][0] < 0.5 {
+                    *i = *copies.entry(*i).or_insert_with(|| {
+                        positions.push(positions[*i as usize]);
+                        uvs.push([1.0, uvs[*i as usize][1]]);
+                        positions.len() as u32 - 1
+                    });
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::depth_map;
+    use super::*;
+
+    #[test]
+    fn seam_triangles_use_their_own_vertices() {
+        let depth = depth_map(&["0123", "3210", "1111"], 4);
+        for projection in [Projection::Cylinder, Projection::Sphere, Projection::Dome] {
+            let mesh = Mesh::wrapped(&depth, &Dimensions::default(), projection, true, None);
+            assert_eq!(mesh.positions.len(), mesh.uvs.len());
+            for &triangle in &mesh.triangles {
+                let u = triangle.map(|i| mesh.uvs[i as usize][0]);
+                let spread = u[0].max(u[1]).max(u[2]) - u[0].min(u[1]).min(u[2]);
+                assert!(spread <= 0.5, "{projection:?} triangle spans u {u:?}");
+            }
+            assert!(mesh.uvs.iter().any(|&[u, _]| u == 1.0));
+        }
+    }
+}
@@ -0,0 +1,297 @@
+// Bins depths are counted in when placing thresholds
+const BINS: usize = 256;
+
+/// Where the boundaries between layers fall
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Quantizer {
+    /// Equally spaced across the whole depth range
+    #[default]
+    Uniform,
+    /// Multi-level Otsu, separating the image's tonal clusters as cleanly as
+    /// possible
+    Otsu,
+    /// K-means clustering of the depths, a layer per cluster
+    KMeans,
+    /// Equal numbers of pixels on every layer, as histogram equalization does
+    Equalize,
+    /// The builder's own thresholds
+    Manual,
+}
+
+impl Quantizer {
+    pub const ALL: [Quantizer; 5] = [
+        Quantizer::Uniform,
+        Quantizer::Otsu,
+        Quantizer::KMeans,
+        Quantizer::Equalize,
+        Quantizer::Manual,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Quantizer::Uniform => "Uniform",
+            Quantizer::Otsu => "Otsu",
+            Quantizer::KMeans => "K-means",
+            Quantizer::Equalize => "Equal population",
+            Quantizer::Manual => "Manual",
+        }
+    }
+
+    /// The `layers - 1` ascending thresholds between layers for these depths;
+    /// a depth at or above the n-th threshold is on layer n or higher. Only
+    /// the depths marked in `mask` are considered. Manual placement uses
+    /// `manual` when it holds the right number of thresholds and uniform ones
+    /// otherwise.
+    pub fn thresholds(
+        self,
+        values: &[f32],
+        mask: Option<&[bool]>,
+        layers: u8,
+        manual: &[f32],
+    ) -> Vec<f32> {
+        let count = layers.max(2) as usize - 1;
+        let opaque = || {
+            values
+                .iter()
+                .enumerate()
+                .filter(move |&(i, _)| mask.is_none_or(|mask| mask[i]))
+                .map(|(_, &value)| value)
+        };
+
+        match self {
+            Quantizer::Manual if manual.len() == count => {
+                let mut thresholds = manual.to_vec();
+                thresholds.sort_by(f32::total_cmp);
+                thresholds
+            }
+            Quantizer::Uniform | Quantizer::Manual => uniform(count),
+            Quantizer::Equalize => {
+                let mut sorted: Vec<f32> = opaque().collect();
+                if sorted.is_empty() {
+                    return uniform(count);
+                }
+                sorted.sort_by(f32::total_cmp);
+                (1..=count)
+                    .map(|k| sorted[(k * sorted.len() / (count + 1)).min(sorted.len() - 1)])
+                    .collect()
+            }
+            Quantizer::Otsu => otsu(&histogram(opaque()), count),
+            Quantizer::KMeans => kmeans(&histogram(opaque()), count),
+        }
+    }
+}
+
+// Halfway between the depths of neighbouring layers
+fn uniform(count: usize) -> Vec<f32> {
+    (0..count)
+        .map(|k| (k as f32 + 0.5) / count as f32)
+        .collect()
+}
+
+fn histogram(values: impl Iterator<Item = f32>) -> [f64; BINS] {
+    let mut histogram = [0.0; BINS];
+    for value in values {
+        histogram[bin(value)] += 1.0;
+    }
+    histogram
+}
+
+fn bin(value: f32) -> usize {
+    (value.clamp(0.0, 1.0) * (BINS - 1) as f32).round() as usize
+}
+
+// Depth on the boundary between a bin and the next
+fn boundary(bin: usize) -> f32 {
+    (bin as f32 + 0.5) / (BINS - 1) as f32
+}
+
+// Split the bins into `count + 1` runs with the least variance within them,
+// which is what maximizing Otsu's variance between classes amounts to
+fn otsu(histogram: &[f64; BINS], count: usize) -> Vec<f32> {
+    let classes = count + 1;
+    if classes > BINS {
+        return uniform(count);
+    }
+
+    // Prefix sums of the pixel count, depth and squared depth
+    let mut n = [0.0; BINS + 1];
+    let mut sum = [0.0; BINS + 1];
+    let mut squares = [0.0; BINS + 1];
+    for (i, &weight) in histogram.iter().enumerate() {
+        let depth = i as f64 / (BINS - 1) as f64;
+        n[i + 1] = n[i] + weight;
+        sum[i + 1] = sum[i] + weight * depth;
+        squares[i + 1] = squares[i] + weight * depth * depth;
+    }
+    // Spread of the depths in bins start..end around their mean
+    let spread = |start: usize, end: usize| {
+        let weight = n[end] - n[start];
+        if weight <= 0.0 {
+            return 0.0;
+        }
+        let total = sum[end] - sum[start];
+        (squares[end] - squares[start]) - total * total / weight
+    };
+
+    // cost[k][end]: least spread of the first `end` bins split into k + 1 runs,
+    // and where its last run starts
+    let mut cost = vec![vec![f64::INFINITY; BINS + 1]; classes];
+    let mut start = vec![vec![0; BINS + 1]; classes];
+    cost[0] = (0..=BINS).map(|end| spread(0, end)).collect();
+    for k in 1..classes {
+        for end in k + 1..=BINS {
+            for split in k..end {
+                let candidate = cost[k - 1][split] + spread(split, end);
+                if candidate < cost[k][end] {
+                    cost[k][end] = candidate;
+                    start[k][end] = split;
+                }
+            }
+        }
+    }
+
+    let mut thresholds = Vec::with_capacity(count);
+    let mut end = BINS;
+    for k in (1..classes).rev() {
+        end = start[k][end];
+        thresholds.push(boundary(end - 1));
+    }
+    thresholds.reverse();
+    thresholds
+}
+
+// Lloyd's algorithm on the histogram, starting from equal-population centres
+fn kmeans(histogram: &[f64; BINS], count: usize) -> Vec<f32> {
+    let classes = count + 1;
+    let total: f64 = histogram.iter().sum();
+    if total <= 0.0 {
+        return uniform(count);
+    }
+
+    let depth = |bin: usize| bin as f64 / (BINS - 1) as f64;
+    let mut centres: Vec<f64> = (0..classes)
+        .map(|k| {
+            let target = (k as f64 + 0.5) / classes as f64 * total;
+            let mut seen = 0.0;
+            let bin = (0..BINS)
+                .find(|&bin| {
+                    seen += histogram[bin];
+                    seen >= target
+                })
+                .unwrap_or(BINS - 1);
+            depth(bin)
+        })
+        .collect();
+
+    for _ in 0..100 {
+        let thresholds: Vec<f64> = centres
+            .windows(2)
+            .map(|pair| (pair[0] + pair[1]) / 2.0)
+            .collect();
+        let mut sums = vec![0.0; classes];
+        let mut weights = vec![0.0; classes];
+        for (bin, &weight) in histogram.iter().enumerate() {
+            let class = thresholds.partition_point(|&threshold| threshold <= depth(bin));
+            sums[class] += weight * depth(bin);
+            weights[class] += weight;
+        }
+
+        // Empty clusters keep their centre
+        let moved: Vec<f64> = (0..classes)
+            .map(|k| match weights[k] > 0.0 {
+                true => sums[k] / weights[k],
+                false => centres[k],
+            })
+            .collect();
+        let settled = moved
+            .iter()
+            .zip(&centres)
+            .all(|(a, b)| (a - b).abs() < 1e-6);
+        centres = moved;
+        centres.sort_by(f64::total_cmp);
+        if settled {
+            break;
+        }
+    }
+
+    centres
+        .windows(2)
+        .map(|pair| ((pair[0] + pair[1]) / 2.0) as f32)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FITTED: [Quantizer; 3] = [Quantizer::Otsu, Quantizer::KMeans, Quantizer::Equalize];
+
+    // `count` depths spread a little around each centre
+    fn clusters(centres: &[f32], count: usize) -> Vec<f32> {
+        centres
+            .iter()
+            .flat_map(|&centre| (0..count).map(move |i| centre + (i % 5) as f32 * 0.01 - 0.02))
+            .collect()
+    }
+
+    fn level(thresholds: &[f32], value: f32) -> usize {
+        thresholds.partition_point(|&threshold| threshold <= value)
+    }
+
+    #[test]
+    fn two_clusters_are_split_between_them() {
+        let values = clusters(&[0.2, 0.7], 50);
+        for quantizer in FITTED {
+            let thresholds = quantizer.thresholds(&values, None, 2, &[]);
+            assert_eq!(thresholds.len(), 1);
+            assert!(
+                thresholds[0] > 0.22 && thresholds[0] <= 0.68,
+                "{quantizer:?} put it at {}",
+                thresholds[0]
+            );
+        }
+    }
+
+    #[test]
+    fn three_clusters_take_a_layer_each() {
+        let values = clusters(&[0.1, 0.45, 0.9], 40);
+        for quantizer in FITTED {
+            let thresholds = quantizer.thresholds(&values, None, 3, &[]);
+            let levels: Vec<usize> = values.iter().map(|&v| level(&thresholds, v)).collect();
+            let expected: Vec<usize> = (0..3).flat_map(|k| [k; 40]).collect();
+            assert_eq!(levels, expected, "{quantizer:?} gave {thresholds:?}");
+        }
+    }
+
+    #[test]
+    fn flat_depths_give_ordered_thresholds() {
+        let values = vec![0.5; 64];
+        for quantizer in Quantizer::ALL {
+            let thresholds = quantizer.thresholds(&values, None, 5, &[]);
+            assert_eq!(thresholds.len(), 4);
+            assert!(
+                thresholds.windows(2).all(|pair| pair[0] <= pair[1]),
+                "{quantizer:?} gave {thresholds:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn masked_depths_are_ignored() {
+        let mut values = clusters(&[0.2, 0.7], 50);
+        values.extend([0.95; 200]);
+        let mask: Vec<bool> = (0..values.len()).map(|i| i < 100).collect();
+        for quantizer in FITTED {
+            let thresholds = quantizer.thresholds(&values, Some(&mask), 2, &[]);
+            assert!(thresholds[0] > 0.22 && thresholds[0] <= 0.68);
+        }
+    }
+
+    #[test]
+    fn manual_thresholds_need_one_per_boundary() {
+        let manual = Quantizer::Manual.thresholds(&[], None, 3, &[0.8, 0.3]);
+        assert_eq!(manual, [0.3, 0.8]);
+        let fallback = Quantizer::Manual.thresholds(&[], None, 4, &[0.8, 0.3]);
+        assert_eq!(fallback, Quantizer::Uniform.thresholds(&[], None, 4, &[]));
+    }
+}
@@ -0,0 +1,200 @@
+use crate::mesh::Mesh;
+use image::{Rgb, RgbImage};
+
+// Vertical field of view of the camera
+const FOV: f32 = 35.0 * std::f32::consts::PI / 180.0;
+// Light direction in view space, from the upper left of the viewer
+const LIGHT: [f32; 3] = [-0.4, 0.6, 1.0];
+const AMBIENT: f32 = 0.25;
+// Matte clay, so the relief reads by its shading alone
+const CLAY: [f32; 3] = [0.86, 0.82, 0.76];
+
+/// Viewpoint of the software preview, orbiting the centre of the mesh
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Camera {
+    /// Rotation around the model's vertical axis, in radians
+    pub yaw: f32,
+    /// Tilt towards or away from the viewer, in radians
+    pub pitch: f32,
+    /// Distance from the model, where 1.0 just fits it in view
+    pub distance: f32,
+    /// Shift of the model across the view, right and down, in view heights
+    pub offset: [f32; 2],
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            yaw: 0.35,
+            pitch: -0.5,
+            distance: 1.0,
+            offset: [0.0, 0.0],
+        }
+    }
+}
+
+impl Camera {
+    /// Turn around the model by a drag of `dx`, `dy` in fractions of the
+    /// view's width and height
+    pub fn orbit(&mut self, dx: f32, dy: f32) {
+        let limit = std::f32::consts::FRAC_PI_2;
+        self.yaw += dx * std::f32::consts::PI;
+        self.pitch = (self.pitch + dy * std::f32::consts::PI).clamp(-limit, limit);
+    }
+
+    /// Slide the model across the view by `dx`, `dy` in view heights
+    pub fn pan(&mut self, dx: f32, dy: f32) {
+        self.offset[0] += dx;
+        self.offset[1] += dy;
+    }
+
+    /// Move closer for factors below 1.0 and further away above it
+    pub fn zoom(&mut self, factor: f32) {
+        self.distance = (self.distance * factor).clamp(0.1, 10.0);
+    }
+
+    /// Rasterize `mesh` with flat Lambert shading on a plain background
+    pub fn render(&self, mesh: &Mesh, width: u32, height: u32, background: [u8; 3]) -> RgbImage {
+        let mut img = RgbImage::from_pixel(width, height, Rgb(background));
+        if mesh.positions.is_empty() || width == 0 || height == 0 {
+            return img;
+        }
+
+        // Orbit the centre of the bounding box, far enough back to fit its sphere
+        let mut min = [f32::MAX; 3];
+        let mut max = [f32::MIN; 3];
+        for position in &mesh.positions {
+            for axis in 0..3 {
+                min[axis] = min[axis].min(position[axis]);
+                max[axis] = max[axis].max(position[axis]);
+            }
+        }
+        let center = [0, 1, 2].map(|axis| (min[axis] + max[axis]) / 2.0);
+        let radius = [0, 1, 2]
+            .map(|axis| (max[axis] - min[axis]) / 2.0)
+            .iter()
+            .map(|half| half * half)
+            .sum::<f32>()
+            .sqrt()
+            .max(f32::EPSILON);
+        let eye = radius / (FOV / 2.0).sin() * self.distance;
+
+        let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
+        let (sin_pitch, cos_pitch) = self.pitch.sin_cos();
+        // The relief faces the viewer at rest; yaw turns it about the vertical,
+        // then pitch tips it about the horizontal
+        let view: Vec<[f32; 3]> = mesh
+            .positions
+            .iter()
+            .map(|p| {
+                let [x, y, z] = [p[0] - center[0], p[1] - center[1], p[2] - center[2]];
+                let (x, z) = (x * cos_yaw + z * sin_yaw, z * cos_yaw - x * sin_yaw);
+                let (y, z) = (y * cos_pitch - z * sin_pitch, y * sin_pitch + z * cos_pitch);
+                [x, y, z - eye]
+            })
+            .collect();
+
+        // Project onto the screen, keeping 1/depth for the z-buffer
+        let focal = height as f32 / 2.0 / (FOV / 2.0).tan();
+        let near = radius * 0.01;
+        let projected: Vec<Option<[f32; 3]>> = view
+            .iter()
+            .map(|&[x, y, z]| {
+                let depth = -z;
+                (depth > near).then(|| {
+                    [
+                        width as f32 / 2.0 + x / depth * focal + self.offset[0] * height as f32,
+                        height as f32 / 2.0 - y / depth * focal + self.offset[1] * height as f32,
+                        1.0 / depth,
+                    ]
+                })
+            })
+            .collect();
+
+        let light = normalize(LIGHT);
+        let mut zbuffer = vec![0.0f32; width as usize * height as usize];
+        for &triangle in &mesh.triangles {
+            let [Some(a), Some(b), Some(c)] = triangle.map(|i| projected[i as usize]) else {
+                continue;
+            };
+
+            // Open reliefs show their back too, so light both sides alike
+            let [p, q, r] = triangle.map(|i| view[i as usize]);
+            let normal = normalize(cross(sub(q, p), sub(r, p)));
+            let lambert = dot(normal, light).abs();
+            let shade = AMBIENT + (1.0 - AMBIENT) * lambert;
+            let color = Rgb(CLAY.map(|channel| (channel * shade * 255.0).round() as u8));
+
+            fill(&mut img, &mut zbuffer, [a, b, c], color);
+        }
+        img
+    }
+}
+
+// Scan a screen-space triangle, keeping the nearest surface at each pixel
+fn fill(img: &mut RgbImage, zbuffer: &mut [f32], [a, b, c]: [[f32; 3]; 3], color: Rgb<u8>) {
+    let area = edge(a, b, c);
+    if area.abs() < f32::EPSILON {
+        return;
+    }
+
+    let (width, height) = (img.width() as f32, img.height() as f32);
+    let x0 = a[0].min(b[0]).min(c[0]).floor().max(0.0);
+    let x1 = a[0].max(b[0]).max(c[0]).ceil().min(width - 1.0);
+    let y0 = a[1].min(b[1]).min(c[1]).floor().max(0.0);
+    let y1 = a[1].max(b[1]).max(c[1]).ceil().min(height - 1.0);
+    if x0 > x1 || y0 > y1 {
+        return;
+    }
+
+    for y in y0 as u32..=y1 as u32 {
+        for x in x0 as u32..=x1 as u32 {
+            let p = [x as f32 + 0.5, y as f32 + 0.5, 0.0];
+            let (wa, wb, wc) = (
+                edge(b, c, p) / area,
+                edge(c, a, p) / area,
+                edge(a, b, p) / area,
+            );
+            if wa < 0.0 || wb < 0.0 || wc < 0.0 {
+                continue;
+            }
+            // 1/depth is linear across the screen, so it interpolates directly
+            let inverse = wa * a[2] + wb * b[2] + wc * c[2];
+            let index = (y * img.width() + x) as usize;
+            if inverse > zbuffer[index] {
+                zbuffer[index] = inverse;
+                img.put_pixel(x, y, color);
+            }
+        }
+    }
+}
+
+// Twice the signed area of the triangle a, b, p on screen
+fn edge(a: [f32; 3], b: [f32; 3], p: [f32; 3]) -> f32 {
+    (b[0] - a[0]) * (p[1] - a[1]) - (b[1] - a[1]) * (p[0] - a[0])
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn cross(u: [f32; 3], v: [f32; 3]) -> [f32; 3] {
+    [
+        u[1] * v[2] - u[2] * v[1],
+        u[2] * v[0] - u[0] * v[2],
+        u[0] * v[1] - u[1] * v[0],
+    ]
+}
+
+fn dot(u: [f32; 3], v: [f32; 3]) -> f32 {
+    u[0] * v[0] + u[1] * v[1] + u[2] * v[2]
+}
+
+fn normalize(v: [f32; 3]) -> [f32; 3] {
+    let len = dot(v, v).sqrt();
+    if len > 0.0 {
+        [v[0] / len, v[1] / len, v[2] / len]
+    } else {
+        [0.0, 0.0, 0.0]
+    }
+}
@@ -0,0 +1,388 @@
+use crate::depth::DepthMap;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+/// Settings for cutting a depth map into paper sheets for a shadow box
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StencilOptions {
+    /// Width of the image on every sheet
+    pub width_mm: f32,
+    /// Solid border added around the image
+    pub frame_mm: f32,
+    /// Bridge islands to the rest of their sheet so each cuts as one piece
+    pub connect: bool,
+    pub bridge_mm: f32,
+}
+
+impl Default for StencilOptions {
+    fn default() -> Self {
+        StencilOptions {
+            width_mm: 100.0,
+            frame_mm: 5.0,
+            connect: true,
+            bridge_mm: 1.0,
+        }
+    }
+}
+
+/// Cut lines of one sheet: the opaque pixels at or above `level`, plus the frame and
+/// bridges. Each outline is a closed loop in millimetres with Y pointing down;
+/// holes are loops inside other loops.
+#[derive(Clone, Debug)]
+pub struct Sheet {
+    pub level: u8,
+    pub outlines: Vec<Vec<[f32; 2]>>,
+}
+
+/// One sheet per depth layer, from the back (level 0, uncut but for the
+/// outline) to the front
+#[derive(Clone, Debug)]
+pub struct Stencils {
+    pub width_mm: f32,
+    pub height_mm: f32,
+    pub sheets: Vec<Sheet>,
+}
+
+impl Stencils {
+    pub fn build(depth: &DepthMap, options: &StencilOptions) -> Self {
+        let pitch = options.width_mm / depth.width.max(1) as f32;
+        let frame = (options.frame_mm.max(0.0) / pitch).round() as usize;
+        let bridge = (options.bridge_mm.max(0.0) / pitch / 2.0).floor() as usize;
+        let (width, height) = (depth.width + 2 * frame, depth.height + 2 * frame);
+
+        let sheets = (0..depth.layers)
+            .map(|level| {
+                let mut mask = Mask {
+                    width,
+                    height,
+                    cells: vec![false; width * height],
+                };
+                for y in 0..height {
+                    for x in 0..width {
+                        let (ix, iy) = (x.wrapping_sub(frame), y.wrapping_sub(frame));
+                        mask.cells[y * width + x] = ix >= depth.width
+                            || iy >= depth.height
+                            || (depth.opaque(ix, iy) && depth.level(ix, iy) >= level);
+                    }
+                }
+                if options.connect {
+                    mask.connect(bridge);
+                }
+
+                let outlines = mask
+                    .contours()
+                    .into_iter()
+                    .map(|contour| {
+                        contour
+                            .into_iter()
+                            .map(|(x, y)| [x as f32 / 2.0 * pitch, y as f32 / 2.0 * pitch])
+                            .collect()
+                    })
+                    .collect();
+                Sheet { level, outlines }
+            })
+            .collect();
+
+        Stencils {
+            width_mm: width as f32 * pitch,
+            height_mm: height as f32 * pitch,
+            sheets,
+        }
+    }
+}
+
+// Which pixels of a sheet keep their paper
+struct Mask {
+    width: usize,
+    height: usize,
+    cells: Vec<bool>,
+}
+
+impl Mask {
+    fn get(&self, x: isize, y: isize) -> bool {
+        x >= 0
+            && y >= 0
+            && x < self.width as isize
+            && y < self.height as isize
+            && self.cells[y as usize * self.width + x as usize]
+    }
+
+    fn neighbours(&self, i: usize) -> impl Iterator<Item = usize> + use<> {
+        let (width, height) = (self.width, self.height);
+        let (x, y) = (i % width, i / width);
+        [
+            (x > 0).then(|| i - 1),
+            (x + 1 < width).then(|| i + 1),
+            (y > 0).then(|| i - width),
+            (y + 1 < height).then(|| i + width),
+        ]
+        .into_iter()
+        .flatten()
+    }
+
+    // Label 4-connected regions of paper; returns each cell's label and the
+    // number of cells per label
+    fn components(&self) -> (Vec<usize>, Vec<usize>) {
+        let mut labels = vec![usize::MAX; self.cells.len()];
+        let mut sizes = Vec::new();
+        let mut stack = Vec::new();
+
+        for start in 0..self.cells.len() {
+            if !self.cells[start] || labels[start] != usize::MAX {
+                continue;
+            }
+            let label = sizes.len();
+            let mut size = 0;
+            labels[start] = label;
+            stack.push(start);
+            while let Some(i) = stack.pop() {
+                size += 1;
+                for j in self.neighbours(i) {
+                    if self.cells[j] && labels[j] == usize::MAX {
+                        labels[j] = label;
+                        stack.push(j);
+                    }
+                }
+            }
+            sizes.push(size);
+        }
+
+        (labels, sizes)
+    }
+
+    // Join every region to the largest one (the frame, when there is one) along
+    // the shortest paths through empty cells, widened by `half_width` on each side
+    fn connect(&mut self, half_width: usize) {
+        let (labels, sizes) = self.components();
+        let Some(root) = (0..sizes.len()).max_by_key(|&label| sizes[label]) else {
+            return;
+        };
+
+        let mut members: Vec<Vec<usize>> = vec![Vec::new(); sizes.len()];
+        for (i, &label) in labels.iter().enumerate() {
+            if label != usize::MAX {
+                members[label].push(i);
+            }
+        }
+
+        let mut joined = vec![false; sizes.len()];
+        let mut distance = vec![u32::MAX; self.cells.len()];
+        let mut parent = vec![usize::MAX; self.cells.len()];
+        let mut queue = BinaryHeap::new();
+
+        joined[root] = true;
+        for &i in &members[root] {
+            distance[i] = 0;
+            queue.push(Reverse((0, i)));
+        }
+
+        while let Some(Reverse((d, i))) = queue.pop() {
+            if d > distance[i] {
+                continue;
+            }
+            for j in self.neighbours(i) {
+                let label = labels[j];
+                if label != usize::MAX && !joined[label] {
+                    // Reached another region: lay a bridge back to the joined paper
+                    let mut path = Vec::new();
+                    let mut k = i;
+                    while distance[k] > 0 {
+                        path.push(k);
+                        k = parent[k];
+                    }
+                    for k in path {
+                        self.paint(k, half_width);
+                        distance[k] = 0;
+                        queue.push(Reverse((0, k)));
+                    }
+                    joined[label] = true;
+                    for &k in &members[label] {
+                        distance[k] = 0;
+                        queue.push(Reverse((0, k)));
+                    }
+                } else if label == usize::MAX && d + 1 < distance[j] {
+                    distance[j] = d + 1;
+                    parent[j] = i;
+                    queue.push(Reverse((d + 1, j)));
+                }
+            }
+        }
+    }
+
+    fn paint(&mut self, i: usize, half_width: usize) {
+        let (x, y) = (i % self.width, i / self.width);
+        for py in y.saturating_sub(half_width)..=(y + half_width).min(self.height - 1) {
+            for px in x.saturating_sub(half_width)..=(x + half_width).min(self.width - 1) {
+                self.cells[py * self.width + px] = true;
+            }
+        }
+    }
+
+    // Marching squares over the pixel centres, in half-pixel units. Diagonal
+    // neighbours stay apart, matching the 4-connected regions the bridges join.
+    fn contours(&self) -> Vec<Vec<(isize, isize)>> {
+        // Corners of a square clockwise from top-left; edge k runs from corner k to k + 1
+        const CORNERS: [(isize, isize); 4] = [(0, 0), (1, 0), (1, 1), (0, 1)];
+
+        let mut next: HashMap<(isize, isize), (isize, isize)> = HashMap::new();
+        for y in -1..self.height as isize {
+            for x in -1..self.width as isize {
+                let inside = CORNERS.map(|(dx, dy)| self.get(x + dx, y + dy));
+                let corner = |k: usize| {
+                    let (dx, dy) = CORNERS[k];
+                    (2 * (x + dx) + 1, 2 * (y + dy) + 1)
+                };
+                let midpoint = |k: usize| {
+                    let ((ax, ay), (bx, by)) = (corner(k), corner((k + 1) % 4));
+                    ((ax + bx) / 2, (ay + by) / 2)
+                };
+
+                // Each segment cuts off a corner of paper, or runs straight across
+                let crossings: Vec<usize> = (0..4)
+                    .filter(|&k| inside[k] != inside[(k + 1) % 4])
+                    .collect();
+                let segments: Vec<(usize, usize, usize)> = match crossings[..] {
+                    [a, b] => {
+                        let solid = (0..4).find(|&k| inside[k]).unwrap_or(0);
+                        vec![(a, b, solid)]
+                    }
+                    [_, _, _, _] => (0..4)
+                        .filter(|&k| inside[k])
+                        .map(|k| ((k + 3) % 4, k, k))
+                        .collect(),
+                    _ => Vec::new(),
+                };
+
+                for (a, b, solid) in segments {
+                    let (p, q, c) = (midpoint(a), midpoint(b), corner(solid));
+                    // Keep paper on the same side of every segment so they chain up
+                    let cross = (q.0 - p.0) * (c.1 - p.1) - (q.1 - p.1) * (c.0 - p.0);
+                    if cross > 0 {
+                        next.insert(p, q);
+                    } else {
+                        next.insert(q, p);
+                    }
+                }
+            }
+        }
+
+        let mut contours = Vec::new();
+        while let Some(&start) = next.keys().next() {
+            let mut contour = vec![start];
+            let mut point = next.remove(&start).unwrap();
+            while point != start {
+                contour.push(point);
+                point = next.remove(&point).unwrap();
+            }
+            contours.push(straighten(contour));
+        }
+        contours
+    }
+}
+
+// Drop points in the middle of straight runs
+fn straighten(contour: Vec<(isize, isize)>) -> Vec<(isize, isize)> {
+    let n = contour.len();
+    (0..n)
+        .filter(|&i| {
+            let (a, b, c) = (contour[(i + n - 1) % n], contour[i], contour[(i + 1) % n]);
+            (b.0 - a.0) * (c.1 - b.1) != (b.1 - a.1) * (c.0 - b.0)
+        })
+        .map(|i| contour[i])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Mask from rows of '#' for paper and '.' for cut-out cells
+    fn mask(rows: &[&str]) -> Mask {
+        Mask {
+            width: rows[0].len(),
+            height: rows.len(),
+            cells: rows
+                .iter()
+                .flat_map(|row| row.bytes().map(|b| b == b'#'))
+                .collect(),
+        }
+    }
+
+    // Twice the area enclosed by a loop; the sign gives its direction
+    fn area(contour: &[(isize, isize)]) -> isize {
+        let n = contour.len();
+        (0..n)
+            .map(|i| {
+                let ((ax, ay), (bx, by)) = (contour[i], contour[(i + 1) % n]);
+                ax * by - bx * ay
+            })
+            .sum()
+    }
+
+    // Every step of a loop moves to a neighbouring half-pixel grid point
+    fn assert_closed(contour: &[(isize, isize)]) {
+        assert!(contour.len() >= 3);
+        for (i, &(ax, ay)) in contour.iter().enumerate() {
+            let (bx, by) = contour[(i + 1) % contour.len()];
+            assert!((ax, ay) != (bx, by));
+            assert!(ax.abs_diff(bx) == ay.abs_diff(by) || ax == bx || ay == by);
+        }
+    }
+
+    #[test]
+    fn square_gives_one_loop() {
+        let contours = mask(&["....", ".##.", ".##.", "...."]).contours();
+        assert_eq!(contours.len(), 1);
+        assert_closed(&contours[0]);
+        // The pixel centres span 2 by 2 half-pixels, and the loop runs half a pixel outside them
+        let xs = contours[0].iter().map(|p| p.0);
+        let ys = contours[0].iter().map(|p| p.1);
+        assert_eq!((xs.clone().min(), xs.max()), (Some(2), Some(6)));
+        assert_eq!((ys.clone().min(), ys.max()), (Some(2), Some(6)));
+    }
+
+    #[test]
+    fn ring_gives_an_outline_and_a_hole() {
+        let contours = mask(&[".....", ".###.", ".#.#.", ".###.", "....."]).contours();
+        assert_eq!(contours.len(), 2);
+        contours.iter().for_each(|contour| assert_closed(contour));
+        let (outer, hole) = if area(&contours[0]).abs() > area(&contours[1]).abs() {
+            (&contours[0], &contours[1])
+        } else {
+            (&contours[1], &contours[0])
+        };
+        assert!(area(outer).signum() == -area(hole).signum());
+        let within = |p: &(isize, isize)| p.0 > 2 && p.0 < 8 && p.1 > 2 && p.1 < 8;
+        assert!(hole.iter().all(within));
+        assert!(!outer.iter().all(within));
+    }
+
+    #[test]
+    fn bridged_ring_keeps_its_hole() {
+        let mut mask = mask(&[
+            "#########",
+            "#.......#",
+            "#..###..#",
+            "#..#.#..#",
+            "#..###..#",
+            "#.......#",
+            "#########",
+        ]);
+        assert_eq!(mask.components().1.len(), 2);
+        mask.connect(0);
+        assert_eq!(mask.components().1.len(), 1);
+        // The frame's outline, the gap around the ring, and the ring's hole
+        let contours = mask.contours();
+        assert_eq!(contours.len(), 3);
+        contours.iter().for_each(|contour| assert_closed(contour));
+        let hole = |p: &(isize, isize)| p.0 > 6 && p.0 < 12 && p.1 > 4 && p.1 < 10;
+        assert!(contours.iter().any(|contour| contour.iter().all(hole)));
+    }
+
+    #[test]
+    fn diagonal_saddles_stay_apart() {
+        let contours = mask(&["#.#", ".#.", "#.#"]).contours();
+        assert_eq!(contours.len(), 5);
+        contours.iter().for_each(|contour| assert_closed(contour));
+    }
+}
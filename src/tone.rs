@@ -0,0 +1,156 @@
+/// Levels and a tone curve, remapping the image before it's split into layers
+/// so more depth goes to the tones that matter
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tone {
+    /// Input that becomes the lowest depth; darker pixels clip to it
+    pub black: f32,
+    /// Input that becomes the highest depth; brighter pixels clip to it
+    pub white: f32,
+    /// Midtone adjustment after the black and white points; above 1.0 lifts them
+    pub gamma: f32,
+    /// Points the curve passes through after levels, as `[input, output]` in
+    /// 0.0..=1.0 sorted by input; at least the two ends
+    pub curve: Vec<[f32; 2]>,
+}
+
+impl Default for Tone {
+    fn default() -> Self {
+        Tone {
+            black: 0.0,
+            white: 1.0,
+            gamma: 1.0,
+            curve: vec![[0.0, 0.0], [1.0, 1.0]],
+        }
+    }
+}
+
+impl Tone {
+    /// Whether every value maps to itself
+    pub fn is_identity(&self) -> bool {
+        *self == Tone::default()
+    }
+
+    /// Apply the black point, white point and gamma
+    pub fn levels(&self, value: f32) -> f32 {
+        let span = (self.white - self.black).max(f32::EPSILON);
+        let value = ((value - self.black) / span).clamp(0.0, 1.0);
+        value.powf(1.0 / self.gamma.max(f32::EPSILON))
+    }
+
+    /// Follow the curve through its points, smoothly but without overshooting
+    /// them
+    pub fn curve(&self, value: f32) -> f32 {
+        let points = &self.curve;
+        match points.len() {
+            0 => return value,
+            1 => return points[0][1],
+            _ => {}
+        }
+        let last = points.len() - 1;
+        if value <= points[0][0] {
+            return points[0][1];
+        } else if value >= points[last][0] {
+            return points[last][1];
+        }
+
+        // Monotone cubic Hermite interpolation, with harmonic mean tangents
+        let slope = |i: usize| {
+            let dx = points[i + 1][0] - points[i][0];
+            if dx > 0.0 {
+                (points[i + 1][1] - points[i][1]) / dx
+            } else {
+                0.0
+            }
+        };
+        let tangent = |i: usize| {
+            if i == 0 {
+                slope(0)
+            } else if i == last {
+                slope(last - 1)
+            } else {
+                let (before, after) = (slope(i - 1), slope(i));
+                // Flat at peaks and valleys, so the curve never overshoots a point
+                if before * after <= 0.0 {
+                    0.0
+                } else {
+                    2.0 / (1.0 / before + 1.0 / after)
+                }
+            }
+        };
+
+        let i = points
+            .windows(2)
+            .position(|pair| value < pair[1][0])
+            .unwrap_or(last - 1);
+        let ([x0, y0], [x1, y1]) = (points[i], points[i + 1]);
+        let dx = x1 - x0;
+        if dx <= 0.0 {
+            return y1;
+        }
+        let t = (value - x0) / dx;
+        let (t2, t3) = (t * t, t * t * t);
+        let value = (2.0 * t3 - 3.0 * t2 + 1.0) * y0
+            + (t3 - 2.0 * t2 + t) * dx * tangent(i)
+            + (-2.0 * t3 + 3.0 * t2) * y1
+            + (t3 - t2) * dx * tangent(i + 1);
+        value.clamp(0.0, 1.0)
+    }
+
+    /// Apply levels and then the curve
+    pub fn map(&self, value: f32) -> f32 {
+        self.curve(self.levels(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn samples() -> impl Iterator<Item = f32> {
+        (0..=200).map(|i| i as f32 / 200.0)
+    }
+
+    #[test]
+    fn rising_curve_stays_monotone_through_its_points() {
+        let tone = Tone {
+            curve: vec![[0.0, 0.0], [0.2, 0.6], [0.5, 0.65], [0.9, 0.7], [1.0, 1.0]],
+            ..Tone::default()
+        };
+        for &[x, y] in &tone.curve {
+            assert!((tone.curve(x) - y).abs() < 1e-6, "misses ({x}, {y})");
+        }
+        let values: Vec<f32> = samples().map(|v| tone.curve(v)).collect();
+        assert!(values.windows(2).all(|pair| pair[0] <= pair[1] + 1e-6));
+        assert_eq!((values[0], values[200]), (0.0, 1.0));
+    }
+
+    #[test]
+    fn curve_does_not_overshoot_a_peak() {
+        let tone = Tone {
+            curve: vec![[0.0, 0.2], [0.5, 0.8], [1.0, 0.3]],
+            ..Tone::default()
+        };
+        assert!(samples().all(|v| (0.2..=0.8).contains(&tone.curve(v))));
+        assert_eq!((tone.curve(0.0), tone.curve(1.0)), (0.2, 0.3));
+    }
+
+    #[test]
+    fn default_levels_are_the_identity() {
+        let tone = Tone::default();
+        assert!(tone.is_identity());
+        assert!(samples().all(|v| (tone.levels(v) - v).abs() < 1e-6));
+        assert!(samples().all(|v| (tone.map(v) - v).abs() < 1e-6));
+    }
+
+    #[test]
+    fn levels_stretch_between_black_and_white() {
+        let tone = Tone {
+            black: 0.2,
+            white: 0.6,
+            ..Tone::default()
+        };
+        assert_eq!(tone.levels(0.1), 0.0);
+        assert!((tone.levels(0.4) - 0.5).abs() < 1e-6);
+        assert_eq!(tone.levels(0.8), 1.0);
+    }
+}